@@ -0,0 +1,452 @@
+//! Command line of the `backend` binary. Besides serving, it can run single probes and
+//! decodes so that media problems can be reproduced without the studio.
+
+use std::net::SocketAddr;
+
+use serde_json::json;
+
+use crate::{
+    decoder::{
+        DECODER, DecoderKey, color::ColorOptions, deinterlace::DeinterlaceOptions,
+        pixel_format::PixelFormat, source::sequence_for, source_dimensions,
+    },
+    ffmpeg::{
+        StreamSelector,
+        command::{audio_peaks, write_rgba_image},
+        hw_decoder::extract_frame_hw_rgba,
+        hwaccel, probe_audio_duration_ms, probe_streams, probe_video_codec, probe_video_color,
+        probe_video_dimensions, probe_video_duration_ms, probe_video_field_order, probe_video_fps,
+        probe_video_frames, probe_video_geometry,
+    },
+    server,
+};
+
+pub const USAGE: &str = "\
+usage: backend [serve] [--addr HOST:PORT]
+       backend probe <file> [--stream N]
+       backend frame <file> --frame N [--size WxH] [--stream N] [--direct] -o <out.png>
+       backend peaks <file> [--bins N] [--stream N]
+
+frame writes raw RGBA when the output ends in .rgba, otherwise ffmpeg encodes it.
+--direct decodes with a one-shot ffmpeg call instead of the cached decoder pipeline.
+frame probes hardware decoding first, like the server at startup; set
+FRAMESCRIPT_HWACCEL=off to skip that and decode in software.";
+
+const DEFAULT_ADDR: ([u8; 4], u16) = ([127, 0, 0, 1], 3000);
+/// Sample rate the waveform is computed at, matching the studio's audio context.
+const PEAKS_SAMPLE_RATE: u32 = 48_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Serve {
+        addr: SocketAddr,
+    },
+    Probe {
+        path: String,
+        stream: StreamSelector,
+    },
+    Frame {
+        path: String,
+        frame: u32,
+        size: Option<(u32, u32)>,
+        stream: StreamSelector,
+        direct: bool,
+        output: String,
+    },
+    Peaks {
+        path: String,
+        bins: Option<usize>,
+        stream: StreamSelector,
+    },
+    Help,
+}
+
+impl Command {
+    /// Parse the arguments after the program name. No arguments means `serve`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let Some(name) = args.next() else {
+            return Ok(Self::Serve {
+                addr: DEFAULT_ADDR.into(),
+            });
+        };
+        if name.starts_with("--") && name != "--help" {
+            // `backend --addr ...` is `backend serve --addr ...`.
+            return Self::parse_command("serve", std::iter::once(name).chain(args));
+        }
+        Self::parse_command(&name, args)
+    }
+
+    fn parse_command(name: &str, mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut path = None;
+        let mut addr = None;
+        let mut frame = None;
+        let mut size = None;
+        let mut stream = StreamSelector::default();
+        let mut direct = false;
+        let mut output = None;
+        let mut bins = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {flag}"))
+            };
+            match arg.as_str() {
+                "--addr" => addr = Some(parse_value::<SocketAddr>(&value(&arg)?, &arg)?),
+                "--frame" => frame = Some(parse_value::<u32>(&value(&arg)?, &arg)?),
+                "--size" => size = Some(parse_size(&value(&arg)?)?),
+                "--stream" => stream.index = Some(parse_value::<u32>(&value(&arg)?, &arg)?),
+                "--bins" => bins = Some(parse_value::<usize>(&value(&arg)?, &arg)?),
+                "-o" | "--output" => output = Some(value(&arg)?),
+                "--direct" => direct = true,
+                "-h" | "--help" => return Ok(Self::Help),
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(format!("unknown option {flag}"));
+                }
+                _ if path.is_none() => path = Some(arg),
+                _ => return Err(format!("unexpected argument {arg}")),
+            }
+        }
+
+        let file = move || path.ok_or_else(|| format!("{name}: missing <file>"));
+        match name {
+            "serve" => Ok(Self::Serve {
+                addr: addr.unwrap_or_else(|| DEFAULT_ADDR.into()),
+            }),
+            "probe" => Ok(Self::Probe {
+                path: file()?,
+                stream,
+            }),
+            "frame" => Ok(Self::Frame {
+                path: file()?,
+                frame: frame.ok_or_else(|| "frame: missing --frame N".to_string())?,
+                size,
+                stream,
+                direct,
+                output: output.ok_or_else(|| "frame: missing -o <out.png>".to_string())?,
+            }),
+            "peaks" => Ok(Self::Peaks {
+                path: file()?,
+                bins,
+                stream,
+            }),
+            "help" | "--help" => Ok(Self::Help),
+            other => Err(format!("unknown command {other}")),
+        }
+    }
+
+    /// Run the command. Results go to stdout as JSON; `serve` only returns on error.
+    pub async fn run(self) -> Result<(), String> {
+        match self {
+            Self::Serve { addr } => server::run(addr)
+                .await
+                .map_err(|error| format!("server failed: {error}")),
+            Self::Probe { path, stream } => probe(path, stream).await,
+            Self::Frame {
+                path,
+                frame,
+                size,
+                stream,
+                direct,
+                output,
+            } => extract_frame(path, frame, size, stream, direct, output).await,
+            Self::Peaks { path, bins, stream } => peaks(path, bins, stream).await,
+            Self::Help => {
+                println!("{USAGE}");
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str, flag: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {flag}: {value}"))
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("invalid --size {value}, expected WxH"))?;
+    let width = parse_value::<u32>(width, "--size")?;
+    let height = parse_value::<u32>(height, "--size")?;
+    if width == 0 || height == 0 {
+        return Err(format!("invalid --size {value}"));
+    }
+    Ok((width, height))
+}
+
+fn print_json(value: &serde_json::Value) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(value)
+        .map_err(|error| format!("failed to encode json: {error}"))?;
+    println!("{text}");
+    Ok(())
+}
+
+/// Run the blocking ffprobe/ffmpeg helpers off the runtime threads.
+async fn blocking<T: Send + 'static>(
+    task: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|error| format!("task failed: {error}"))?
+}
+
+async fn probe(path: String, stream: StreamSelector) -> Result<(), String> {
+    let report = blocking(move || {
+        let video = stream.specifier('v');
+        let audio = stream.specifier('a');
+        let geometry = probe_video_geometry(&path, &video).ok();
+        let codec = probe_video_codec(&path, &video).ok();
+        let field_order = probe_video_field_order(&path, &video).ok();
+        Ok(json!({
+            "path": path,
+            "streams": probe_streams(&path)?,
+            "video": {
+                "stream": video,
+                "durationMs": probe_video_duration_ms(&path, &video).ok(),
+                "frames": probe_video_frames(&path, &video).ok(),
+                "fps": probe_video_fps(&path, &video).ok(),
+                "dimensions": probe_video_dimensions(&path, &video).ok(),
+                "codedDimensions": geometry.map(|g| (g.coded_width, g.coded_height)),
+                "sampleAspectRatio": geometry.map(|g| g.sample_aspect_ratio),
+                "rotation": geometry.map(|g| g.rotation),
+                "codec": codec.as_ref().map(|codec| &codec.codec_name),
+                "alpha": codec.as_ref().map(|codec| codec.has_alpha),
+                "interlaced": field_order.map(|order| order.is_interlaced()),
+                "color": probe_video_color(&path, &video).ok(),
+            },
+            "audio": {
+                "stream": audio,
+                "durationMs": probe_audio_duration_ms(&path, &audio).ok(),
+            },
+        }))
+    })
+    .await?;
+    print_json(&report)
+}
+
+async fn extract_frame(
+    path: String,
+    frame: u32,
+    size: Option<(u32, u32)>,
+    stream: StreamSelector,
+    direct: bool,
+    output: String,
+) -> Result<(), String> {
+    let sequence = sequence_for(&path, None);
+    let probe_path = match &sequence {
+        Some(sequence) => sequence.first_file(&path),
+        None => path.clone(),
+    };
+    let (width, height) = match size {
+        Some(size) => size,
+        None => source_dimensions(&probe_path, &stream.specifier('v'))
+            .await
+            .ok_or_else(|| format!("failed to probe size of {path}, pass --size"))?,
+    };
+
+    // Streams decode in software until the probe has run.
+    blocking(|| {
+        hwaccel::probe();
+        Ok(())
+    })
+    .await?;
+
+    let rgba = if direct {
+        let path = path.clone();
+        blocking(move || extract_frame_hw_rgba(&path, frame as usize, width, height)).await?
+    } else {
+        let decoder = DECODER
+            .cached_decoder(DecoderKey {
+                path: path.clone(),
+                width,
+                height,
+                format: PixelFormat::Rgba,
+                color: ColorOptions::default(),
+                filters: Vec::new(),
+                sequence,
+                deinterlace: DeinterlaceOptions::default(),
+                stream,
+                preview: false,
+                session_id: 0,
+            })
+            .await;
+        decoder.get_frame(frame).await.to_vec()
+    };
+
+    let is_raw = output.to_ascii_lowercase().ends_with(".rgba");
+    let written = output.clone();
+    blocking(move || {
+        if is_raw {
+            std::fs::write(&output, &rgba)
+                .map_err(|error| format!("failed to write {output}: {error}"))
+        } else {
+            write_rgba_image(&rgba, width, height, &output)
+        }
+    })
+    .await?;
+    eprintln!("wrote frame {frame} of {path} ({width}x{height}) to {written}");
+    Ok(())
+}
+
+async fn peaks(path: String, bins: Option<usize>, stream: StreamSelector) -> Result<(), String> {
+    let report = blocking(move || {
+        let specifier = stream.specifier('a');
+        let duration_ms = probe_audio_duration_ms(&path, &specifier)?;
+        let channels = probe_streams(&path)?
+            .into_iter()
+            .filter(|info| info.kind == "audio")
+            .find(|info| match (stream.index, stream.language.as_deref()) {
+                (Some(index), _) => info.kind_index == index,
+                (None, Some(language)) => info.language.as_deref() == Some(language),
+                (None, None) => true,
+            })
+            .and_then(|info| info.channels)
+            .unwrap_or(2) as usize;
+
+        // Same bin count as the studio's waveform view.
+        let duration_sec = duration_ms as f64 / 1000.0;
+        let bins = bins
+            .unwrap_or_else(|| (duration_sec * 120.0).round().clamp(400.0, 4000.0) as usize)
+            .max(1);
+        let total_samples = (duration_sec * PEAKS_SAMPLE_RATE as f64) as usize;
+        let samples_per_bin = (total_samples / bins).max(1);
+        let peaks = audio_peaks(
+            &path,
+            &specifier,
+            channels,
+            PEAKS_SAMPLE_RATE,
+            samples_per_bin,
+            bins,
+        )?;
+        Ok(json!({
+            "path": path,
+            "stream": specifier,
+            "durationSec": duration_sec,
+            "sampleRate": PEAKS_SAMPLE_RATE,
+            "channels": channels,
+            "peaks": peaks,
+        }))
+    })
+    .await?;
+    print_json(&report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        Command::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn serves_by_default() {
+        let default = Command::Serve {
+            addr: DEFAULT_ADDR.into(),
+        };
+        assert_eq!(parse(&[]), Ok(default.clone()));
+        assert_eq!(parse(&["serve"]), Ok(default));
+
+        let addr: SocketAddr = "0.0.0.0:4000".parse().unwrap();
+        assert_eq!(
+            parse(&["--addr", "0.0.0.0:4000"]),
+            Ok(Command::Serve { addr })
+        );
+        assert_eq!(
+            parse(&["serve", "--addr", "0.0.0.0:4000"]),
+            Ok(Command::Serve { addr })
+        );
+    }
+
+    #[test]
+    fn parses_frame() {
+        assert_eq!(
+            parse(&[
+                "frame", "clip.mp4", "--frame", "12", "--size", "640x360", "--stream", "1",
+                "--direct", "-o", "out.png",
+            ]),
+            Ok(Command::Frame {
+                path: "clip.mp4".to_string(),
+                frame: 12,
+                size: Some((640, 360)),
+                stream: StreamSelector {
+                    index: Some(1),
+                    language: None,
+                },
+                direct: true,
+                output: "out.png".to_string(),
+            })
+        );
+        assert_eq!(
+            parse(&["frame", "clip.mp4", "-o", "out.png"]),
+            Err("frame: missing --frame N".to_string())
+        );
+        assert_eq!(
+            parse(&["frame", "clip.mp4", "--frame", "1"]),
+            Err("frame: missing -o <out.png>".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_probe_and_peaks() {
+        assert_eq!(
+            parse(&["probe", "clip.mp4"]),
+            Ok(Command::Probe {
+                path: "clip.mp4".to_string(),
+                stream: StreamSelector::default(),
+            })
+        );
+        assert_eq!(
+            parse(&["peaks", "clip.wav", "--bins", "100"]),
+            Ok(Command::Peaks {
+                path: "clip.wav".to_string(),
+                bins: Some(100),
+                stream: StreamSelector::default(),
+            })
+        );
+        assert_eq!(parse(&["probe"]), Err("probe: missing <file>".to_string()));
+    }
+
+    #[test]
+    fn parses_help() {
+        assert_eq!(parse(&["help"]), Ok(Command::Help));
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["probe", "-h"]), Ok(Command::Help));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(
+            parse(&["encode"]),
+            Err("unknown command encode".to_string())
+        );
+        assert_eq!(
+            parse(&["probe", "clip.mp4", "--fast"]),
+            Err("unknown option --fast".to_string())
+        );
+        assert_eq!(
+            parse(&["probe", "a.mp4", "b.mp4"]),
+            Err("unexpected argument b.mp4".to_string())
+        );
+        assert_eq!(
+            parse(&["frame", "clip.mp4", "--frame"]),
+            Err("missing value for --frame".to_string())
+        );
+        assert_eq!(
+            parse(&["frame", "clip.mp4", "--frame", "-1"]),
+            Err("invalid value for --frame: -1".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1920x1080"), Ok((1920, 1080)));
+        assert_eq!(parse_size("64X36"), Ok((64, 36)));
+        assert!(parse_size("0x10").is_err());
+        assert!(parse_size("1920").is_err());
+    }
+}
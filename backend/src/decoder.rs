@@ -1,36 +1,175 @@
+pub mod cache;
+pub mod color;
+pub mod deinterlace;
+pub mod disk_cache;
+pub mod filter;
+#[cfg(feature = "libav")]
+mod libav;
+pub mod memory;
+mod parallel;
+pub mod pixel_format;
+pub mod proxy;
+pub mod render_session;
+pub mod resample;
+pub mod scheduler;
+pub mod source;
+pub mod watch;
+
 use std::{
     collections::{BTreeSet, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    ops::Range,
     process::Stdio,
     sync::{
-        Arc, LazyLock, Mutex, RwLock,
+        Arc, LazyLock, Mutex, OnceLock, RwLock,
         atomic::{AtomicBool, AtomicUsize, Ordering},
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use tokio::{io::AsyncReadExt, process::Command, sync::Notify, time::timeout};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    process::Command,
+    sync::Notify,
+    task::JoinHandle,
+    time::timeout,
+};
 
 use crate::{
-    ffmpeg::{bin::ffmpeg_path, hw_decoder, probe_video_fps},
+    decoder::{
+        cache::DecoderUsage,
+        color::{ColorOptions, scale_filter, zscale_available},
+        deinterlace::{DeinterlaceOptions, FieldRate},
+        filter::{VideoFilter, crop_filter, post_filters},
+        pixel_format::PixelFormat,
+        render_session::RenderPlan,
+        resample::FitMode,
+        scheduler::{Priority, Slot},
+        source::{ImageSequence, is_animated_image, is_exr, is_image_codec},
+    },
+    ffmpeg::{
+        FieldOrder, StreamSelector, VideoCodecInfo, VideoColorInfo, VideoGeometry,
+        bin::ffmpeg_path, estimate_video_frames, hw_decoder, hwaccel, probe_video_codec,
+        probe_video_color, probe_video_dimensions, probe_video_field_order, probe_video_fps,
+        probe_video_frames, probe_video_geometry,
+    },
     future::SharedManualFuture,
+    metrics::{self, DecodeMode, Fallback},
 };
 use tracing::warn;
 
 pub static DECODER: LazyLock<Decoder> = LazyLock::new(|| Decoder::new());
-static FPS_CACHE: LazyLock<Mutex<HashMap<String, f64>>> =
+// Probe results keyed by source path.
+type ProbeCache<T> = LazyLock<Mutex<HashMap<String, T>>>;
+static FPS_CACHE: ProbeCache<f64> = LazyLock::new(|| Mutex::new(HashMap::new()));
+/// Frame counts, keyed like the other probes. A count being probed is shared by everyone
+/// asking for it; failed probes are dropped so that the next request tries again.
+static FRAME_COUNT_CACHE: ProbeCache<SharedManualFuture<Option<u32>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static DIMENSION_CACHE: ProbeCache<Option<(u32, u32)>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static COLOR_CACHE: ProbeCache<Option<VideoColorInfo>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
+static GEOMETRY_CACHE: ProbeCache<Option<VideoGeometry>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static CODEC_CACHE: ProbeCache<Option<VideoCodecInfo>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static FIELD_ORDER_CACHE: ProbeCache<Option<FieldOrder>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Stream specifier of the first video stream, which most sources only have.
+pub const DEFAULT_VIDEO_STREAM: &str = "v:0";
+
+fn probe_key(path: &str, stream: &str) -> String {
+    if stream == DEFAULT_VIDEO_STREAM {
+        path.to_string()
+    } else {
+        format!("{path}#{stream}")
+    }
+}
+
+/// Run `probe` on a blocking thread once per path and stream and remember the outcome.
+async fn cached_probe<T: Clone + Send + 'static>(
+    cache: &'static ProbeCache<Option<T>>,
+    path: &str,
+    stream: &str,
+    probe: impl FnOnce(&str, &str) -> Result<T, String> + Send + 'static,
+) -> Option<T> {
+    let key = probe_key(path, stream);
+    if let Some(value) = cache.lock().unwrap().get(&key) {
+        return value.clone();
+    }
+
+    watch::track(path);
+    let (owned_path, owned_stream) = (path.to_string(), stream.to_string());
+    let value = tokio::task::spawn_blocking(move || probe(&owned_path, &owned_stream))
+        .await
+        .ok()
+        .and_then(|result| result.ok());
+
+    cache.lock().unwrap().insert(key, value.clone());
+    value
+}
 
 pub struct Decoder {
     map: Mutex<HashMap<DecoderKey, CachedDecoder>>,
 }
 
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Decoder {
-    fn new() -> Self {
+    /// An empty set of decoders. The server uses the shared [`DECODER`]; the memory budget
+    /// is global either way.
+    pub fn new() -> Self {
         Self {
             map: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Number of live decoders.
+    pub fn len(&self) -> usize {
+        self.map.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// [`decode_size`] for a decoder like `key`. A cropped preview reuses the largest bucket
+    /// already open for the same source instead of starting a native decoder; the crop
+    /// layout maps the reduced cache back onto source pixels.
+    pub fn decode_size(
+        &self,
+        key: &DecoderKey,
+        source: Option<(u32, u32)>,
+        width: u32,
+        height: u32,
+        fit: FitMode,
+    ) -> (u32, u32) {
+        let size = decode_size(source, width, height, fit);
+        let Some(source) = source.filter(|_| fit == FitMode::Crop && key.preview) else {
+            return size;
+        };
+        let map = self.map.lock().unwrap();
+        ResolutionBucket::ALL
+            .into_iter()
+            .rev()
+            .map(|bucket| bucket.dimensions(source))
+            .find(|&(width, height)| {
+                map.contains_key(&DecoderKey {
+                    width,
+                    height,
+                    ..key.clone()
+                })
+            })
+            .unwrap_or(size)
+    }
+
     pub async fn cached_decoder(&self, key: DecoderKey) -> CachedDecoder {
         let mut generated = false;
         let decoder = self
@@ -45,6 +184,8 @@ impl Decoder {
             .clone();
 
         if generated {
+            watch::track(&decoder.inner.path);
+            memory::ensure_monitor();
             decoder.schedule_gc().await;
         }
 
@@ -82,7 +223,45 @@ impl Decoder {
             tokio::time::sleep(Duration::from_millis(50)).await;
         }
 
-        ENTIRE_CACHE_SIZE.store(0, Ordering::Relaxed);
+        cache::reset();
+        watch::reset();
+    }
+
+    /// Memory held by every live decoder.
+    pub fn usage(&self) -> Vec<DecoderUsage> {
+        let decoders = self
+            .map
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        decoders.iter().map(CachedDecoder::usage).collect()
+    }
+
+    /// Close the decoders of `path` and/or `session_id` (all of them when both are `None`)
+    /// and free their frames. Returns the number of decoders and bytes released.
+    pub fn purge(&self, path: Option<&str>, session_id: Option<u64>) -> (usize, usize) {
+        let removed = {
+            let mut map = self.map.lock().unwrap();
+            let mut removed = Vec::new();
+            map.retain(|key, decoder| {
+                let matches = path.is_none_or(|path| key.path == path)
+                    && session_id.is_none_or(|session_id| key.session_id == session_id);
+                if matches {
+                    removed.push(decoder.clone());
+                }
+                !matches
+            });
+            removed
+        };
+
+        let mut bytes = 0;
+        for decoder in &removed {
+            decoder.close();
+            bytes += decoder.release_frames();
+        }
+        (removed.len(), bytes)
     }
 
     pub fn clear_session(&self, session_id: u64) {
@@ -102,7 +281,9 @@ impl Decoder {
 
         for decoder in removed {
             decoder.close();
+            decoder.release_frames();
         }
+        cache::forget_session(session_id);
     }
 }
 
@@ -112,23 +293,278 @@ const STREAM_IDLE_TIMEOUT: Duration = Duration::from_millis(300);
 const STREAM_RESTART_GAP: u32 = 90;
 const RECENT_FRAME_CACHE: usize = 6;
 const FAST_SEEK_BACKOFF_SEC: f64 = 2.0;
-
+// Playback faster than this decodes only every n-th frame and skips non-reference frames.
+const HIGH_SPEED_RATE: f64 = 2.0;
+const MAX_PLAYBACK_STRIDE: u32 = 8;
+// Number of frames decoded (and cached) per chunk while playing backwards.
+const REVERSE_CHUNK_FRAMES: u32 = 24;
+
+/// Upper bound of the cache budget. Under memory pressure the effective budget is lower
+/// (see [`memory`]).
 pub fn set_max_cache_size(bytes: usize) {
     MAX_CACHE_SIZE.store(bytes.max(1024 * 1024), Ordering::Relaxed);
+    memory::refresh();
 }
 
+/// Bytes cached and the budget currently evicted against.
 pub fn get_cache_usage() -> (usize, usize) {
     (
         ENTIRE_CACHE_SIZE.load(Ordering::Relaxed),
-        MAX_CACHE_SIZE.load(Ordering::Relaxed),
+        memory::effective_cache_size(),
     )
 }
 
+/// How a request past the last frame of a source is answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutOfRangePolicy {
+    /// Repeat the last frame.
+    Hold,
+    /// Wrap around to the first frame.
+    Loop,
+    /// Play backwards from the end, then forwards again.
+    PingPong,
+    /// Return a fully transparent frame.
+    Transparent,
+}
+
+impl OutOfRangePolicy {
+    /// Map `frame` onto a frame inside `0..frame_count`.
+    /// `None` means the request should be answered with a transparent frame.
+    pub fn resolve(self, frame: u32, frame_count: u32) -> Option<u32> {
+        if frame < frame_count {
+            return Some(frame);
+        }
+        if frame_count == 0 {
+            return None;
+        }
+
+        match self {
+            OutOfRangePolicy::Hold => Some(frame_count - 1),
+            OutOfRangePolicy::Loop => Some(frame % frame_count),
+            OutOfRangePolicy::PingPong => {
+                if frame_count == 1 {
+                    return Some(0);
+                }
+                let period = (frame_count - 1) * 2;
+                let phase = frame % period;
+                if phase < frame_count {
+                    Some(phase)
+                } else {
+                    Some(period - phase)
+                }
+            }
+            OutOfRangePolicy::Transparent => None,
+        }
+    }
+}
+
+/// Resolutions a source is decoded at, relative to its native size.
+/// Requested sizes are produced from the smallest bucket that still covers them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResolutionBucket {
+    Native,
+    Half,
+    Quarter,
+    Eighth,
+}
+
+impl ResolutionBucket {
+    const ALL: [ResolutionBucket; 4] = [
+        ResolutionBucket::Eighth,
+        ResolutionBucket::Quarter,
+        ResolutionBucket::Half,
+        ResolutionBucket::Native,
+    ];
+
+    pub fn divisor(self) -> u32 {
+        match self {
+            ResolutionBucket::Native => 1,
+            ResolutionBucket::Half => 2,
+            ResolutionBucket::Quarter => 4,
+            ResolutionBucket::Eighth => 8,
+        }
+    }
+
+    pub fn select(source: (u32, u32), requested: (u32, u32), fit: FitMode) -> Self {
+        let scale_x = requested.0 as f64 / source.0.max(1) as f64;
+        let scale_y = requested.1 as f64 / source.1.max(1) as f64;
+        let needed = match fit {
+            FitMode::Stretch | FitMode::Fill => scale_x.max(scale_y),
+            FitMode::Fit => scale_x.min(scale_y),
+            FitMode::Crop => 1.0,
+        };
+
+        Self::ALL
+            .into_iter()
+            .find(|bucket| 1.0 / bucket.divisor() as f64 >= needed - 1e-6)
+            .unwrap_or(ResolutionBucket::Native)
+    }
+
+    pub fn dimensions(self, source: (u32, u32)) -> (u32, u32) {
+        match self {
+            ResolutionBucket::Native => source,
+            bucket => {
+                let divisor = bucket.divisor();
+                (
+                    ((source.0 / divisor) & !1).max(2),
+                    ((source.1 / divisor) & !1).max(2),
+                )
+            }
+        }
+    }
+}
+
+/// Drop the decoders, probe results and proxy of a source that changed on disk.
+/// Returns the number of decoders closed.
+pub fn invalidate_source(path: &str) -> usize {
+    let (decoders, _) = DECODER.purge(Some(path), None);
+    forget_probes(&FPS_CACHE, path);
+    forget_probes(&FRAME_COUNT_CACHE, path);
+    forget_probes(&DIMENSION_CACHE, path);
+    forget_probes(&COLOR_CACHE, path);
+    forget_probes(&GEOMETRY_CACHE, path);
+    forget_probes(&CODEC_CACHE, path);
+    forget_probes(&FIELD_ORDER_CACHE, path);
+    proxy::forget(path);
+    hwaccel::forget(path);
+    decoders
+}
+
+/// Remove every stream's entry for `path` (see [`probe_key`]).
+fn forget_probes<T>(cache: &ProbeCache<T>, path: &str) {
+    cache.lock().unwrap().retain(|key, _| {
+        key != path
+            && !key
+                .strip_prefix(path)
+                .is_some_and(|rest| rest.starts_with('#'))
+    });
+}
+
+/// Displayed (width, height) of a video, probed once per path.
+pub async fn source_dimensions(path: &str, stream: &str) -> Option<(u32, u32)> {
+    cached_probe(&DIMENSION_CACHE, path, stream, probe_video_dimensions).await
+}
+
+/// Codec and alpha support of a video, probed once per path.
+pub async fn source_codec(path: &str, stream: &str) -> Option<VideoCodecInfo> {
+    cached_probe(&CODEC_CACHE, path, stream, probe_video_codec).await
+}
+
+/// Number of frames of a video, probed once per path. Only sources whose container does
+/// not record it are decoded to count them.
+async fn source_frame_count(path: &str, stream: &str) -> Option<u32> {
+    let (path, stream) = (path.to_string(), stream.to_string());
+    shared_frame_count(probe_key(&path, &stream), move || {
+        estimate_video_frames(&path, &stream)
+            .or_else(|_| probe_video_frames(&path, &stream))
+            .ok()
+            .map(|count| count.min(u32::MAX as u64) as u32)
+    })
+    .await
+}
+
+/// The frame count under `key`, running `probe` on a blocking thread unless another
+/// request already is.
+async fn shared_frame_count(
+    key: String,
+    probe: impl FnOnce() -> Option<u32> + Send + 'static,
+) -> Option<u32> {
+    let (future, first) = {
+        let mut counts = FRAME_COUNT_CACHE.lock().unwrap();
+        match counts.get(&key) {
+            Some(future) => (future.clone(), false),
+            None => {
+                let future = SharedManualFuture::new();
+                counts.insert(key.clone(), future.clone());
+                (future, true)
+            }
+        }
+    };
+    if first {
+        // Probed in its own task, so that the waiters are answered even if this request
+        // is dropped.
+        let future = future.clone();
+        tokio::spawn(async move {
+            let count = tokio::task::spawn_blocking(probe).await.ok().flatten();
+            future.complete(Arc::new(count)).await;
+            if count.is_none() {
+                let mut counts = FRAME_COUNT_CACHE.lock().unwrap();
+                if counts
+                    .get(&key)
+                    .and_then(SharedManualFuture::get_now)
+                    .is_some_and(|count| count.is_none())
+                {
+                    counts.remove(&key);
+                }
+            }
+        });
+    }
+    *future.get().await
+}
+
+/// Whether a source is an animated image, from its codec and, for image codecs, its
+/// frame count.
+pub async fn source_is_animated(path: &str, stream: &str) -> bool {
+    let Some(codec) = source_codec(path, stream)
+        .await
+        .and_then(|codec| codec.codec_name)
+    else {
+        return false;
+    };
+    if !is_image_codec(&codec) {
+        return false;
+    }
+    let frame_count = source_frame_count(path, stream).await.unwrap_or(0);
+    is_animated_image(&codec, frame_count)
+}
+
+/// Field order of a video, probed once per path.
+pub async fn source_field_order(path: &str, stream: &str) -> Option<FieldOrder> {
+    cached_probe(&FIELD_ORDER_CACHE, path, stream, probe_video_field_order).await
+}
+
+/// Coded size, sample aspect ratio and rotation of a video, probed once per path.
+pub async fn source_geometry(path: &str, stream: &str) -> Option<VideoGeometry> {
+    cached_probe(&GEOMETRY_CACHE, path, stream, probe_video_geometry).await
+}
+
+/// Color description of a video, probed once per path.
+pub async fn source_color(path: &str, stream: &str) -> Option<VideoColorInfo> {
+    cached_probe(&COLOR_CACHE, path, stream, probe_video_color).await
+}
+
+/// Size the decoder should produce for a request of `width`x`height` from a source
+/// displayed at `source`. Falls back to the requested size when the source is unknown.
+pub fn decode_size(
+    source: Option<(u32, u32)>,
+    width: u32,
+    height: u32,
+    fit: FitMode,
+) -> (u32, u32) {
+    match source {
+        Some(source) => ResolutionBucket::select(source, (width, height), fit).dimensions(source),
+        None => (width, height),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct DecoderKey {
     pub path: String,
+    /// Decode resolution, normally one of the [`ResolutionBucket`] sizes of the source
+    /// (see [`decode_size`]), not the size the client asked for.
     pub width: u32,
     pub height: u32,
+    pub format: PixelFormat,
+    pub color: ColorOptions,
+    pub filters: Vec<VideoFilter>,
+    /// Set when `path` is a sequence pattern (see [`source::sequence_for`]).
+    pub sequence: Option<ImageSequence>,
+    pub deinterlace: DeinterlaceOptions,
+    /// Video stream to decode, for sources with more than one.
+    pub stream: StreamSelector,
+    /// Preview decoders may read a [`proxy`] of the source instead of the source itself.
+    pub preview: bool,
     pub session_id: u64,
 }
 
@@ -142,15 +578,41 @@ struct Inner {
     path: String,
     width: u32,
     height: u32,
+    format: PixelFormat,
+    color: ColorOptions,
+    filters: Vec<VideoFilter>,
+    sequence: Option<ImageSequence>,
+    deinterlace: DeinterlaceOptions,
+    /// ffmpeg specifier of the decoded video stream.
+    stream: String,
+    preview: bool,
     session_id: u64,
     frames: RwLock<HashMap<u32, SharedManualFuture<Vec<u8>>>>,
     pending_frames: Mutex<BTreeSet<u32>>,
     pinned_frame: Mutex<Option<u32>>,
     recent_frames: Mutex<VecDeque<u32>>,
+    playback_rate: Mutex<f64>,
+    /// Priority of the most recent request, used when the stream needs an ffmpeg slot.
+    priority: Mutex<Priority>,
     stream_notify: Notify,
     stream_running: AtomicBool,
     closed: AtomicBool,
     running_decode_tasks: AtomicUsize,
+    /// Bytes of completed frames in `frames` that count against the cache budget.
+    cached_bytes: AtomicUsize,
+    /// Key of this decoder's frames in the disk cache; `None` if the source cannot be stat'ed.
+    disk_key: OnceLock<Option<u64>>,
+    /// Frames a render worker will ask for, when its session registered them.
+    render_plan: Mutex<Option<RenderPlan>>,
+    /// Source frames being decoded by chunk processes (see [`parallel`]).
+    chunks: Mutex<Vec<Range<u32>>>,
+    /// Where the next chunk starts.
+    chunk_cursor: Mutex<Option<u32>>,
+    dispatching: AtomicBool,
+    /// The in-process decoder failed on this source; use the ffmpeg CLI from now on.
+    libav_failed: AtomicBool,
+    /// The running stream decodes a proxy, whose frames must not reach the disk cache.
+    reading_proxy: AtomicBool,
 }
 
 impl CachedDecoder {
@@ -159,15 +621,32 @@ impl CachedDecoder {
             path: key.path,
             width: key.width,
             height: key.height,
+            format: key.format,
+            color: key.color,
+            filters: key.filters,
+            sequence: key.sequence,
+            deinterlace: key.deinterlace,
+            stream: key.stream.specifier('v'),
+            preview: key.preview,
             session_id: key.session_id,
             frames: RwLock::new(HashMap::new()),
             pending_frames: Mutex::new(BTreeSet::new()),
             pinned_frame: Mutex::new(None),
             recent_frames: Mutex::new(VecDeque::new()),
+            playback_rate: Mutex::new(1.0),
+            priority: Mutex::new(Priority::default()),
             stream_notify: Notify::new(),
             stream_running: AtomicBool::new(false),
             closed: AtomicBool::new(false),
             running_decode_tasks: AtomicUsize::new(0),
+            cached_bytes: AtomicUsize::new(0),
+            disk_key: OnceLock::new(),
+            render_plan: Mutex::new(None),
+            chunks: Mutex::new(Vec::new()),
+            chunk_cursor: Mutex::new(None),
+            dispatching: AtomicBool::new(false),
+            libav_failed: AtomicBool::new(false),
+            reading_proxy: AtomicBool::new(false),
         };
         Self {
             inner: Arc::new(inner),
@@ -179,9 +658,10 @@ impl CachedDecoder {
 
         tokio::spawn(async move {
             loop {
-                if ENTIRE_CACHE_SIZE.load(Ordering::Relaxed)
-                    >= MAX_CACHE_SIZE.load(Ordering::Relaxed)
-                {
+                if self_clone.inner.closed.load(Ordering::Relaxed) {
+                    break;
+                }
+                if self_clone.inner.over_budget() {
                     let pending_snapshot = {
                         let pending = self_clone.inner.pending_frames.lock().unwrap();
                         pending.clone()
@@ -211,24 +691,138 @@ impl CachedDecoder {
                         if future.is_completed() {
                             let future = frames.remove(&frame_index).unwrap();
 
-                            ENTIRE_CACHE_SIZE
-                                .fetch_sub(future.get_now().unwrap().len(), Ordering::Relaxed);
+                            self_clone.inner.release(future.get_now().unwrap().len());
 
-                            if ENTIRE_CACHE_SIZE.load(Ordering::Relaxed)
-                                < MAX_CACHE_SIZE.load(Ordering::Relaxed)
-                            {
+                            if !self_clone.inner.over_budget() {
                                 break;
                             }
                         }
                     }
                 }
 
-                tokio::time::sleep(Duration::from_secs(5)).await;
+                let _ = timeout(Duration::from_secs(5), memory::PRESSURE.notified()).await;
             }
         });
     }
 
+    /// Tell the stream loop how the client is moving through the source.
+    /// Negative rates mean reverse playback.
+    pub fn set_playback_rate(&self, rate: f64) {
+        if !rate.is_finite() || rate == 0.0 {
+            return;
+        }
+        *self.inner.playback_rate.lock().unwrap() = rate;
+    }
+
+    pub fn set_priority(&self, priority: Priority) {
+        *self.inner.priority.lock().unwrap() = priority;
+    }
+
+    pub fn has_render_plan(&self) -> bool {
+        self.inner.render_plan.lock().unwrap().is_some()
+    }
+
+    /// Decode `frames` (ascending source frames) ahead of a render worker asking for them.
+    pub fn set_render_plan(&self, frames: Vec<u32>) {
+        let first = frames.first().copied().unwrap_or(0);
+        *self.inner.render_plan.lock().unwrap() = Some(RenderPlan::new(frames));
+        self.advance_render_plan(first);
+    }
+
+    /// The render worker got `frame_index`: drop the planned frames before it and queue the
+    /// next ones, so the stream keeps reading sequentially.
+    pub fn advance_render_plan(&self, frame_index: u32) {
+        let (used, next) = match self.inner.render_plan.lock().unwrap().as_mut() {
+            Some(plan) => plan.advance(frame_index),
+            None => return,
+        };
+
+        let pinned = *self.inner.pinned_frame.lock().unwrap();
+        for used_index in used {
+            if Some(used_index) == pinned {
+                continue;
+            }
+            let mut frames = self.inner.frames.write().unwrap();
+            if frames
+                .get(&used_index)
+                .is_some_and(|future| future.is_completed())
+                && let Some(cached) = frames
+                    .remove(&used_index)
+                    .and_then(|future| future.get_now())
+            {
+                self.inner.release(cached.len());
+            }
+        }
+
+        let mut queued = false;
+        for next_index in next {
+            if self.inner.is_chunked(next_index) {
+                continue;
+            }
+            let future = self
+                .inner
+                .frames
+                .write()
+                .unwrap()
+                .entry(next_index)
+                .or_insert_with(SharedManualFuture::new)
+                .clone();
+            if !future.is_completed() {
+                queued |= self.inner.pending_frames.lock().unwrap().insert(next_index);
+            }
+        }
+        if queued {
+            self.ensure_stream_task();
+            self.inner.stream_notify.notify_one();
+        }
+        parallel::dispatch(&self.inner, frame_index);
+    }
+
+    /// Like [`CachedDecoder::get_frame`], but resolves requests past the end of the source
+    /// with `policy` instead of waiting for ffmpeg to run dry.
+    pub async fn get_frame_with_policy(
+        &self,
+        frame_index: u32,
+        policy: Option<OutOfRangePolicy>,
+    ) -> Arc<Vec<u8>> {
+        let Some(policy) = policy else {
+            return self.get_frame(frame_index).await;
+        };
+
+        let Some(frame_count) = self.frame_count().await else {
+            return self.get_frame(frame_index).await;
+        };
+
+        match policy.resolve(frame_index, frame_count) {
+            Some(resolved) => self.get_frame(resolved).await,
+            None => Arc::new(self.inner.format.blank_frame(
+                self.inner.width,
+                self.inner.height,
+                false,
+            )),
+        }
+    }
+
+    async fn frame_count(&self) -> Option<u32> {
+        if let Some(sequence) = self.inner.sequence {
+            let pattern = self.inner.path.clone();
+            let key = format!("{}#{}", pattern, sequence.start());
+            return shared_frame_count(key, move || match sequence.frame_count(&pattern) {
+                0 => None,
+                count => Some(count),
+            })
+            .await;
+        }
+
+        let count = source_frame_count(&self.inner.path, &self.inner.stream).await?;
+        let field_order = source_field_order(&self.inner.path, &self.inner.stream)
+            .await
+            .unwrap_or_default();
+        Some(count.saturating_mul(self.inner.deinterlace.rate_multiplier(field_order)))
+    }
+
     pub async fn get_frame(&self, frame_index: u32) -> Arc<Vec<u8>> {
+        let started = Instant::now();
         let future = {
             let mut frames = self.inner.frames.write().unwrap();
             frames
@@ -238,9 +832,19 @@ impl CachedDecoder {
         };
 
         if let Some(frame) = future.get_now() {
+            metrics::FRAME_CACHED_LATENCY.observe(started.elapsed());
             return self.finish_frame(frame_index, frame);
         }
 
+        if let Some(frame) = self.load_from_disk(frame_index).await {
+            if !future.is_completed() {
+                self.inner.charge(frame.len());
+                future.complete(frame.clone()).await;
+            }
+            metrics::FRAME_CACHED_LATENCY.observe(started.elapsed());
+            return self.finish_frame(frame_index, future.get_now().unwrap_or(frame));
+        }
+
         {
             let mut pinned = self.inner.pinned_frame.lock().unwrap();
             if pinned.is_none() {
@@ -248,7 +852,8 @@ impl CachedDecoder {
             }
         }
 
-        {
+        // A chunk process completes the frame if it claimed it.
+        if !self.inner.is_chunked(frame_index) {
             let mut pending = self.inner.pending_frames.lock().unwrap();
             pending.insert(frame_index);
         }
@@ -278,17 +883,18 @@ impl CachedDecoder {
                                 let frames = self.inner.frames.read().unwrap();
                                 match frames.get(&fallback_index) {
                                     Some(future) => match future.get_now() {
-                                        Some(result) => break result,
+                                        Some(result) => {
+                                            metrics::record_fallback(Fallback::PreviousFrame);
+                                            break result;
+                                        }
                                         None => continue,
                                     },
                                     None => continue,
                                 }
                             }
                             None => {
-                                break Arc::new(generate_empty_frame(
-                                    self.inner.width,
-                                    self.inner.height,
-                                ));
+                                metrics::record_fallback(Fallback::Placeholder);
+                                break Arc::new(self.inner.empty_frame());
                             }
                         }
                     };
@@ -297,9 +903,26 @@ impl CachedDecoder {
             }
         };
 
+        metrics::FRAME_DECODED_LATENCY.observe(started.elapsed());
         self.finish_frame(frame_index, frame)
     }
 
+    /// Look the frame up in the disk cache, if it is enabled.
+    async fn load_from_disk(&self, frame_index: u32) -> Option<Arc<Vec<u8>>> {
+        if !disk_cache::is_enabled() {
+            return None;
+        }
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            let key = inner.disk_key()?;
+            let frame_size = inner.format.frame_size(inner.width, inner.height);
+            disk_cache::load(key, frame_index, frame_size).map(Arc::new)
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
     fn ensure_stream_task(&self) {
         if self.inner.stream_running.swap(true, Ordering::Relaxed) {
             return;
@@ -332,7 +955,7 @@ impl CachedDecoder {
                     let removed = self.inner.frames.write().unwrap().remove(&drop_index);
                     if let Some(future) = removed {
                         if let Some(cached) = future.get_now() {
-                            ENTIRE_CACHE_SIZE.fetch_sub(cached.len(), Ordering::Relaxed);
+                            self.inner.release(cached.len());
                         }
                     }
                 }
@@ -345,46 +968,329 @@ impl CachedDecoder {
         self.inner.closed.store(true, Ordering::Relaxed);
         self.inner.stream_notify.notify_one();
     }
+
+    /// Drop every completed frame and return how many bytes that freed.
+    fn release_frames(&self) -> usize {
+        self.inner
+            .frames
+            .write()
+            .unwrap()
+            .retain(|_, future| !future.is_completed());
+        let bytes = self.inner.cached_bytes.load(Ordering::Relaxed);
+        self.inner.release(bytes);
+        bytes
+    }
+
+    fn usage(&self) -> DecoderUsage {
+        let frames = self
+            .inner
+            .frames
+            .read()
+            .unwrap()
+            .values()
+            .filter(|future| future.is_completed())
+            .count();
+        DecoderUsage {
+            path: self.inner.path.clone(),
+            width: self.inner.width,
+            height: self.inner.height,
+            format: self.inner.format,
+            session_id: self.inner.session_id,
+            frames,
+            bytes: self.inner.cached_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Inner {
+    fn charge(&self, bytes: usize) {
+        self.cached_bytes.fetch_add(bytes, Ordering::Relaxed);
+        cache::charge(self.session_id, bytes);
+    }
+
+    fn release(&self, bytes: usize) {
+        let _ = self
+            .cached_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |cached| {
+                Some(cached.saturating_sub(bytes))
+            });
+        cache::release(self.session_id, bytes);
+    }
+
+    /// Hash of the source file's identity and every setting that changes the pixels.
+    fn disk_key(&self) -> Option<u64> {
+        *self.disk_key.get_or_init(|| {
+            let identity = watch::file_identity(&self.probe_path())?;
+            let mut hasher = disk_cache::StableHasher::default();
+            self.path.hash(&mut hasher);
+            identity.hash(&mut hasher);
+            (self.width, self.height, self.format).hash(&mut hasher);
+            self.color.hash(&mut hasher);
+            self.filters.hash(&mut hasher);
+            self.sequence.hash(&mut hasher);
+            self.deinterlace.hash(&mut hasher);
+            self.stream.hash(&mut hasher);
+            self.preview.hash(&mut hasher);
+            Some(hasher.finish())
+        })
+    }
+
+    /// Finished proxy to decode from instead of the source. Only preview decoders use one,
+    /// and only when nothing they apply depends on the source's own pixels or timing.
+    fn proxy(&self) -> Option<proxy::ReadyProxy> {
+        let eligible = self.preview
+            && self.sequence.is_none()
+            && self.stream == DEFAULT_VIDEO_STREAM
+            && self.color == ColorOptions::default()
+            && self.deinterlace.rate != Some(FieldRate::Field)
+            && !self
+                .filters
+                .iter()
+                .any(|filter| matches!(filter, VideoFilter::Crop { .. }));
+        eligible
+            .then(|| proxy::ready_proxy(&self.path, self.width, self.height))
+            .flatten()
+    }
+
+    /// Write a decoded frame through to the disk cache.
+    fn persist(&self, frame_index: u32, frame: &Arc<Vec<u8>>) {
+        if !disk_cache::is_enabled() || self.reading_proxy.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(key) = self.disk_key() {
+            disk_cache::store(key, frame_index, frame.clone());
+        }
+    }
+
+    fn is_chunked(&self, frame_index: u32) -> bool {
+        self.chunks
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|chunk| chunk.contains(&frame_index))
+    }
+
+    /// Whether the global budget or this session's quota is used up.
+    fn over_budget(&self) -> bool {
+        ENTIRE_CACHE_SIZE.load(Ordering::Relaxed) >= memory::effective_cache_size()
+            || cache::session_over_quota(self.session_id)
+    }
+
+    fn empty_frame(&self) -> Vec<u8> {
+        placeholder_frame(self.format, self.width, self.height)
+    }
+
+    /// File probed for size, codec and color: the first file of a sequence, else the path.
+    fn probe_path(&self) -> String {
+        match &self.sequence {
+            Some(sequence) => sequence.first_file(&self.path),
+            None => self.path.clone(),
+        }
+    }
+}
+
+/// Lines of ffmpeg's stderr kept to explain a failed stream.
+const STDERR_TAIL_LINES: usize = 16;
+
+/// Where a [`FrameStream`] reads frames from.
+enum StreamOutput {
+    Process {
+        child: tokio::process::Child,
+        stdout: tokio::process::ChildStdout,
+        /// Forwards stderr to ours; resolves to its last lines once ffmpeg exits.
+        stderr: Option<JoinHandle<String>>,
+    },
+    /// Handed to a blocking thread for each call; `None` only if one of those panicked.
+    #[cfg(feature = "libav")]
+    Libav(Option<Box<libav::LibavStream>>),
+}
+
+/// Why a stream stopped handing out frames.
+#[derive(Debug)]
+enum StreamError {
+    /// The source ended, e.g. a request past its last frame.
+    End,
+    Failed(String),
 }
 
 struct FrameStream {
-    child: tokio::process::Child,
-    stdout: tokio::process::ChildStdout,
+    output: StreamOutput,
+    /// Held for as long as the stream runs.
+    slot: Slot,
     frame_size: usize,
     next_frame: u32,
-    use_hwaccel: bool,
+    stride: u32,
+    mode: DecodeMode,
+    /// `-hwaccel` method of a CLI stream.
+    hwaccel: Option<String>,
+    frames_read: u32,
 }
 
 impl FrameStream {
     async fn spawn(
-        path: &str,
+        inner: &Inner,
         start_frame: u32,
-        dst_width: u32,
-        dst_height: u32,
         use_hwaccel: bool,
+        stride: u32,
     ) -> Result<Self, String> {
-        let frame_size = (dst_width as usize)
-            .saturating_mul(dst_height as usize)
-            .saturating_mul(4);
+        let slot = acquire_slot(inner).await?;
+        let proxy = inner.proxy();
+        inner
+            .reading_proxy
+            .store(proxy.is_some(), Ordering::Relaxed);
+        let path = match &proxy {
+            Some(proxy) => proxy.path.as_str(),
+            None => inner.path.as_str(),
+        };
+        let probe_path = match &proxy {
+            Some(proxy) => proxy.path.clone(),
+            None => inner.probe_path(),
+        };
+        let animated_image =
+            inner.sequence.is_none() && source_is_animated(&probe_path, &inner.stream).await;
+        let (dst_width, dst_height, format) = (inner.width, inner.height, inner.format);
+        let stride = stride.max(1);
+        let frame_size = format.frame_size(dst_width, dst_height);
         if frame_size == 0 {
             return Err("invalid output size".to_string());
         }
 
-        let fps = {
-            let mut cache = FPS_CACHE.lock().unwrap();
-            if let Some(value) = cache.get(path).copied() {
-                value
-            } else {
-                let value = probe_video_fps(path).unwrap_or(60.0);
-                cache.insert(path.to_string(), value);
-                value
-            }
+        let stream = inner.stream.as_str();
+        let field_order = source_field_order(&probe_path, stream)
+            .await
+            .unwrap_or_default();
+        let deinterlace = inner.deinterlace.filter(field_order);
+        let source_fps = match inner.sequence {
+            Some(sequence) => sequence.fps(),
+            None => cached_fps(path, stream),
+        };
+        // Field-rate deinterlacing turns every field into a frame.
+        let fps = source_fps * inner.deinterlace.rate_multiplier(field_order) as f64;
+        // Sequences start at the requested file and animated images are trimmed by frame
+        // number, so only container video seeks by time.
+        let seekable = inner.sequence.is_none() && !animated_image;
+        let target_sec = if seekable {
+            (start_frame as f64) / fps.max(1.0)
+        } else {
+            0.0
         };
-        let target_sec = (start_frame as f64) / fps.max(1.0);
         let backoff = target_sec.min(FAST_SEEK_BACKOFF_SEC);
         let fast_seek = target_sec - backoff;
 
-        let filter = format!("trim=start_frame=0,scale={}x{}", dst_width, dst_height);
+        // Rotation is applied by us (after scaling) rather than by ffmpeg's autorotate, so
+        // scale to the pre-rotation size. Non-square pixels are fixed by the scale itself.
+        let geometry = source_geometry(&probe_path, stream).await;
+        let rotation = geometry.map(|geometry| geometry.rotation).unwrap_or(0);
+        let (scale_width, scale_height) = if rotation % 180 == 90 {
+            (dst_height, dst_width)
+        } else {
+            (dst_width, dst_height)
+        };
+        let source_height = geometry
+            .map(|geometry| geometry.coded_height)
+            .unwrap_or(scale_height);
+
+        // Hardware decoders drop the alpha plane, so alpha sources always decode in software.
+        let codec = source_codec(&probe_path, stream).await.unwrap_or_default();
+        let use_hwaccel = use_hwaccel && seekable && !codec.has_alpha;
+        // Software until the hwaccel probe has finished.
+        let hwaccel = if use_hwaccel {
+            hwaccel::method_for(&inner.path)
+        } else {
+            None
+        };
+
+        let color = source_color(&probe_path, stream).await.unwrap_or_default();
+        let mut scale = String::new();
+        if let Some(crop) = geometry.and_then(|geometry| crop_filter(&inner.filters, &geometry)) {
+            scale.push_str(&crop);
+            scale.push(',');
+        }
+        scale.push_str(&scale_filter(
+            &color,
+            &inner.color,
+            scale_width,
+            scale_height,
+            source_height,
+            format,
+            zscale_available().await,
+        ));
+        scale.push_str(rotation_filter(rotation));
+        scale.push_str(",setsar=1");
+        scale.push_str(&post_filters(&inner.filters));
+
+        // With a stride, frames are picked on a constant-rate grid so that output frame `n`
+        // is always source frame `start_frame + n * stride`, even when non-reference frames
+        // are dropped by the decoder.
+        let filter = if animated_image {
+            // Frame delays of animated images vary, so stride over decoded frames instead.
+            let select = if stride > 1 {
+                format!("select=not(mod(n\\,{})),", stride)
+            } else {
+                String::new()
+            };
+            format!("trim=start_frame={},{}{}", start_frame, select, scale)
+        } else if stride > 1 {
+            format!(
+                "trim=start={:.6},setpts=PTS-STARTPTS,fps={},select=not(mod(n\\,{})),{}",
+                backoff, fps, stride, scale
+            )
+        } else {
+            format!("trim=start_frame=0,{}", scale)
+        };
+        // Fields have to be recombined before anything moves or resizes lines.
+        let filter = match &deinterlace {
+            Some(deinterlace) => format!("{},{}", deinterlace, filter),
+            None => filter,
+        };
+
+        // Decode in-process where possible. Once that fails, this decoder sticks to the CLI.
+        #[cfg(feature = "libav")]
+        if !inner.libav_failed.load(Ordering::Relaxed)
+            && seekable
+            && stride == 1
+            && codec.alpha_decoder().is_none()
+            && inner.deinterlace.rate_multiplier(field_order) == 1
+        {
+            let chain = match &deinterlace {
+                Some(deinterlace) => format!("{},{}", deinterlace, scale),
+                None => scale.clone(),
+            };
+            let (path, stream) = (path.to_string(), stream.to_string());
+            let opened = tokio::task::spawn_blocking(move || {
+                libav::LibavStream::open(
+                    &path,
+                    &stream,
+                    start_frame,
+                    fps,
+                    &chain,
+                    format,
+                    dst_width,
+                    dst_height,
+                )
+            })
+            .await
+            .unwrap_or_else(|error| Err(format!("libav open panicked: {error}")));
+            match opened {
+                Ok(libav) => {
+                    metrics::record_spawn(DecodeMode::Libav);
+                    return Ok(Self {
+                        output: StreamOutput::Libav(Some(Box::new(libav))),
+                        slot,
+                        frame_size,
+                        next_frame: start_frame,
+                        stride,
+                        mode: DecodeMode::Libav,
+                        hwaccel: None,
+                        frames_read: 0,
+                    });
+                }
+                Err(error) => {
+                    warn!("libav decoder unavailable for {path}: {error}");
+                    inner.libav_failed.store(true, Ordering::Relaxed);
+                }
+            }
+        }
 
         let ffmpeg = ffmpeg_path()?;
         let mut cmd = Command::new(ffmpeg);
@@ -395,13 +1301,33 @@ impl FrameStream {
         if fast_seek > 0.0 {
             cmd.arg("-ss").arg(format!("{:.6}", fast_seek));
         }
-        if use_hwaccel {
-            cmd.arg("-hwaccel").arg("auto");
+        if let Some(method) = &hwaccel {
+            cmd.arg("-hwaccel").arg(method);
+        }
+        if stride > 1 && seekable {
+            cmd.arg("-skip_frame").arg("noref");
+        }
+        if let Some(sequence) = inner.sequence {
+            cmd.arg("-f")
+                .arg("image2")
+                .arg("-framerate")
+                .arg(format!("{:.6}", fps))
+                .arg("-start_number")
+                .arg(sequence.start().saturating_add(start_frame).to_string());
+        }
+        if is_exr(&probe_path) {
+            // EXR holds linear light; have the decoder hand out sRGB.
+            cmd.arg("-apply_trc").arg("iec61966_2_1");
+        }
+        cmd.arg("-noautorotate");
+        if let Some(decoder) = codec.alpha_decoder() {
+            cmd.arg("-c:v").arg(decoder);
         }
         cmd.arg("-i").arg(path);
-        if backoff > 0.0 {
+        if backoff > 0.0 && stride == 1 {
             cmd.arg("-ss").arg(format!("{:.6}", backoff));
         }
+        cmd.arg("-map").arg(format!("0:{}?", stream));
         cmd.arg("-vf")
             .arg(filter)
             .arg("-an")
@@ -410,10 +1336,10 @@ impl FrameStream {
             .arg("-f")
             .arg("rawvideo")
             .arg("-pix_fmt")
-            .arg("rgba")
+            .arg(format.ffmpeg_name())
             .arg("pipe:1");
 
-        cmd.stdout(Stdio::piped()).stderr(Stdio::inherit());
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
 
         let mut child = cmd
             .spawn()
@@ -422,35 +1348,205 @@ impl FrameStream {
             .stdout
             .take()
             .ok_or_else(|| "failed to open ffmpeg stdout".to_string())?;
+        let stderr = child
+            .stderr
+            .take()
+            .map(|stderr| tokio::spawn(forward_stderr(stderr)));
+        metrics::record_spawn(DecodeMode::from_hwaccel(hwaccel.is_some()));
 
         Ok(Self {
-            child,
-            stdout,
+            output: StreamOutput::Process {
+                child,
+                stdout,
+                stderr,
+            },
+            slot,
             frame_size,
             next_frame: start_frame,
-            use_hwaccel,
+            stride,
+            mode: DecodeMode::from_hwaccel(hwaccel.is_some()),
+            hwaccel,
+            frames_read: 0,
         })
     }
 
-    async fn read_next(&mut self) -> Result<Vec<u8>, String> {
-        let mut frame = vec![0u8; self.frame_size];
-        self.stdout
-            .read_exact(&mut frame)
-            .await
-            .map_err(|error| format!("failed to read ffmpeg output: {error}"))?;
-        self.next_frame = self.next_frame.saturating_add(1);
-        Ok(frame)
+    /// The next frame and its index. CLI streams number frames consecutively on the stride
+    /// grid; libav streams go by timestamp and may skip indices.
+    async fn read_next(&mut self) -> Result<(u32, Vec<u8>), StreamError> {
+        let (index, frame) = match &mut self.output {
+            StreamOutput::Process {
+                child,
+                stdout,
+                stderr,
+            } => {
+                let mut frame = vec![0u8; self.frame_size];
+                match stdout.read_exact(&mut frame).await {
+                    Ok(_) => {}
+                    Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                        // Whether ffmpeg ran out of input or gave up shows in its exit status.
+                        let status = child.wait().await;
+                        let tail = match stderr.take() {
+                            Some(stderr) => stderr.await.unwrap_or_default(),
+                            None => String::new(),
+                        };
+                        return Err(match status {
+                            Ok(status) if status.success() => StreamError::End,
+                            Ok(status) => {
+                                StreamError::Failed(format!("ffmpeg exited with {status}: {tail}"))
+                            }
+                            Err(error) => StreamError::Failed(format!("ffmpeg vanished: {error}")),
+                        });
+                    }
+                    Err(error) => {
+                        return Err(StreamError::Failed(format!(
+                            "failed to read ffmpeg output: {error}"
+                        )));
+                    }
+                }
+                (self.next_frame, frame)
+            }
+            #[cfg(feature = "libav")]
+            StreamOutput::Libav(libav) => {
+                match with_libav(libav, |libav| libav.read_next()).await {
+                    Ok(Some(decoded)) => decoded,
+                    Ok(None) => return Err(StreamError::End),
+                    Err(error) => return Err(StreamError::Failed(error)),
+                }
+            }
+        };
+        self.next_frame = match self.mode {
+            DecodeMode::Libav => index.saturating_add(1),
+            _ => index.saturating_add(self.stride),
+        };
+        self.frames_read += 1;
+        Ok((index, frame))
+    }
+
+    /// Continue from `start_frame` without a new process. Only libav streams can.
+    #[cfg_attr(not(feature = "libav"), allow(unused_variables))]
+    async fn seek(&mut self, start_frame: u32) -> bool {
+        match &mut self.output {
+            StreamOutput::Process { .. } => false,
+            #[cfg(feature = "libav")]
+            StreamOutput::Libav(libav) => {
+                match with_libav(libav, move |libav| libav.seek(start_frame)).await {
+                    Ok(()) => {
+                        self.next_frame = start_frame;
+                        true
+                    }
+                    Err(error) => {
+                        warn!("libav seek failed: {error}");
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remember that this stream's way of decoding broke with `error`, so that later streams
+    /// avoid it. A hardware stream only counts as broken when it never got going or the
+    /// error points at the hardware.
+    fn note_failure(&self, inner: &Inner, error: &str) {
+        match self.mode {
+            DecodeMode::Libav => inner.libav_failed.store(true, Ordering::Relaxed),
+            DecodeMode::Hwaccel
+                if self.hwaccel.is_some()
+                    && (self.frames_read == 0 || hwaccel::is_hardware_error(error)) =>
+            {
+                hwaccel::mark_failed(&inner.path)
+            }
+            _ => {}
+        }
     }
 
     async fn shutdown(&mut self) {
-        let _ = self.child.kill().await;
-        let _ = self.child.wait().await;
+        match &mut self.output {
+            StreamOutput::Process { child, .. } => {
+                let _ = child.kill().await;
+                let _ = child.wait().await;
+            }
+            #[cfg(feature = "libav")]
+            StreamOutput::Libav(_) => {}
+        }
+    }
+}
+
+/// Run `work` on the libav stream in `slot` on a blocking thread, handing it back afterwards.
+#[cfg(feature = "libav")]
+async fn with_libav<T: Send + 'static>(
+    slot: &mut Option<Box<libav::LibavStream>>,
+    work: impl FnOnce(&mut libav::LibavStream) -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    let mut libav = slot
+        .take()
+        .ok_or_else(|| "libav decoder was lost".to_string())?;
+    let (libav, result) = tokio::task::spawn_blocking(move || {
+        let result = work(&mut libav);
+        (libav, result)
+    })
+    .await
+    .map_err(|error| format!("libav decoder panicked: {error}"))?;
+    *slot = Some(libav);
+    result
+}
+
+/// Copy ffmpeg's stderr to ours and return its last lines.
+async fn forward_stderr(stderr: tokio::process::ChildStderr) -> String {
+    let mut lines = BufReader::new(stderr).lines();
+    let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    while let Ok(Some(line)) = lines.next_line().await {
+        eprintln!("{line}");
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+    Vec::from(tail).join("\n")
+}
+
+/// Wait for an ffmpeg slot, giving up if the decoder is closed meanwhile.
+async fn acquire_slot(inner: &Inner) -> Result<Slot, String> {
+    let priority = *inner.priority.lock().unwrap();
+    let acquire = scheduler::acquire(priority, inner.session_id);
+    tokio::pin!(acquire);
+    loop {
+        if let Ok(slot) = timeout(STREAM_IDLE_TIMEOUT, &mut acquire).await {
+            return Ok(slot);
+        }
+        if inner.closed.load(Ordering::Relaxed) {
+            return Err("decoder closed".to_string());
+        }
+    }
+}
+
+/// Frame rate of a container source, probed once per path and stream. Blocking.
+fn cached_fps(path: &str, stream: &str) -> f64 {
+    let key = probe_key(path, stream);
+    let mut cache = FPS_CACHE.lock().unwrap();
+    if let Some(value) = cache.get(&key).copied() {
+        value
+    } else {
+        let value = probe_video_fps(path, stream).unwrap_or(60.0);
+        cache.insert(key, value);
+        value
+    }
+}
+
+/// Filters turning a frame by `rotation` degrees clockwise.
+fn rotation_filter(rotation: u32) -> &'static str {
+    match rotation {
+        90 => ",transpose=clock",
+        180 => ",hflip,vflip",
+        270 => ",transpose=cclock",
+        _ => "",
     }
 }
 
 async fn run_stream_loop(inner: Arc<Inner>) {
     let mut stream: Option<FrameStream> = None;
     let mut current_frame: u32 = 0;
+    // Last frame read, shown for indices the source has no frame of.
+    let mut previous_frame: Option<Arc<Vec<u8>>> = None;
 
     loop {
         if inner.closed.load(Ordering::Relaxed) {
@@ -463,53 +1559,74 @@ async fn run_stream_loop(inner: Arc<Inner>) {
         };
 
         let Some(target_frame) = target else {
+            // An idle stream only blocks on its pipe, but still holds a slot others may need.
+            if scheduler::has_waiters()
+                && let Some(mut idle) = stream.take()
+            {
+                idle.shutdown().await;
+            }
             let _ = timeout(STREAM_IDLE_TIMEOUT, inner.stream_notify.notified()).await;
             continue;
         };
 
+        let rate = *inner.playback_rate.lock().unwrap();
+        let stride = playback_stride(rate);
+        let reverse = rate < 0.0;
+
         let restart = match stream.as_ref() {
             None => true,
-            Some(_) => {
-                target_frame < current_frame
-                    || target_frame.saturating_sub(current_frame) > STREAM_RESTART_GAP
+            Some(stream) => {
+                stream.stride != stride
+                    || target_frame < current_frame
+                    || target_frame.saturating_sub(current_frame)
+                        > STREAM_RESTART_GAP.saturating_mul(stride)
             }
         };
 
         if restart {
-            if let Some(mut old) = stream.take() {
-                old.shutdown().await;
-            }
+            // Backwards, decode a whole chunk ending at the target in one pass so the
+            // following requests are served from the cache instead of respawning ffmpeg.
+            let start_frame = if reverse {
+                reverse_chunk_start(target_frame, stride)
+            } else {
+                target_frame
+            };
+            current_frame = start_frame;
+            previous_frame = None;
 
-            stream = match FrameStream::spawn(
-                &inner.path,
-                target_frame,
-                inner.width,
-                inner.height,
-                true,
-            )
-            .await
-            {
-                Ok(stream) => Some(stream),
-                Err(hw_err) => match FrameStream::spawn(
-                    &inner.path,
-                    target_frame,
-                    inner.width,
-                    inner.height,
-                    false,
-                )
-                .await
-                {
-                    Ok(stream) => Some(stream),
-                    Err(sw_err) => {
-                        let _ = hw_err;
-                        let _ = sw_err;
-                        complete_pending_with_fallback(inner.clone()).await;
-                        continue;
-                    }
-                },
+            let seeked = match stream.as_mut() {
+                Some(existing) if existing.stride == stride => existing.seek(start_frame).await,
+                _ => false,
             };
+            if !seeked {
+                let replaced = match stream.take() {
+                    Some(mut old) => {
+                        old.shutdown().await;
+                        true
+                    }
+                    None => false,
+                };
 
-            current_frame = target_frame;
+                stream = match FrameStream::spawn(&inner, start_frame, true, stride).await {
+                    Ok(stream) => Some(stream),
+                    Err(hw_err) => {
+                        match FrameStream::spawn(&inner, start_frame, false, stride).await {
+                            Ok(stream) => Some(stream),
+                            Err(sw_err) => {
+                                warn!(
+                                    "decoder stream spawn failed session={} frame={}: hw: {hw_err}; sw: {sw_err}",
+                                    inner.session_id, start_frame
+                                );
+                                complete_pending_with_fallback(inner.clone()).await;
+                                continue;
+                            }
+                        }
+                    }
+                };
+                if let (true, Some(stream)) = (replaced, stream.as_ref()) {
+                    metrics::record_restart(stream.mode);
+                }
+            }
         }
 
         let Some(stream_ref) = stream.as_mut() else {
@@ -517,6 +1634,13 @@ async fn run_stream_loop(inner: Arc<Inner>) {
         };
 
         while current_frame <= target_frame {
+            if stream_ref.slot.should_yield() {
+                // Requeue behind the more urgent decode; the pending frames keep the loop going.
+                if let Some(mut old) = stream.take() {
+                    old.shutdown().await;
+                }
+                break;
+            }
             if let Some(min_pending) = {
                 let pending = inner.pending_frames.lock().unwrap();
                 pending.iter().next().cloned()
@@ -526,29 +1650,41 @@ async fn run_stream_loop(inner: Arc<Inner>) {
                 }
             }
 
-            let frame = match stream_ref.read_next().await {
-                Ok(frame) => frame,
-                Err(_) if stream_ref.use_hwaccel => {
+            let (frame_index, frame) = match stream_ref.read_next().await {
+                Ok(decoded) => decoded,
+                Err(StreamError::End) => {
+                    // Past the last frame: answer what is left with the fallbacks. The stream
+                    // goes first, as an extract fallback needs its slot.
+                    if let Some(mut old) = stream.take() {
+                        old.shutdown().await;
+                    }
+                    complete_pending_with_fallback(inner.clone()).await;
+                    break;
+                }
+                Err(StreamError::Failed(error)) if stream_ref.mode != DecodeMode::Software => {
+                    let mode = stream_ref.mode;
                     warn!(
-                        "decoder stream hw read failed session={} frame={}",
-                        inner.session_id, current_frame
+                        "decoder stream {} read failed session={} frame={}: {error}",
+                        mode.label(),
+                        inner.session_id,
+                        current_frame
                     );
+                    stream_ref.note_failure(&inner, &error);
                     if let Some(mut old) = stream.take() {
                         old.shutdown().await;
                     }
-                    stream = match FrameStream::spawn(
-                        &inner.path,
-                        current_frame,
-                        inner.width,
-                        inner.height,
-                        false,
-                    )
-                    .await
+                    // After libav, the CLI may still use hardware; after hardware, software.
+                    let use_hwaccel = mode == DecodeMode::Libav;
+                    stream = match FrameStream::spawn(&inner, current_frame, use_hwaccel, stride)
+                        .await
                     {
-                        Ok(stream) => Some(stream),
-                        Err(_) => {
+                        Ok(stream) => {
+                            metrics::record_restart(stream.mode);
+                            Some(stream)
+                        }
+                        Err(error) => {
                             warn!(
-                                "decoder stream sw fallback spawn failed session={} frame={}",
+                                "decoder stream sw fallback spawn failed session={} frame={}: {error}",
                                 inner.session_id, current_frame
                             );
                             complete_pending_with_fallback(inner.clone()).await;
@@ -557,39 +1693,48 @@ async fn run_stream_loop(inner: Arc<Inner>) {
                     };
                     break;
                 }
-                Err(_) => {
+                Err(StreamError::Failed(error)) => {
                     warn!(
-                        "decoder stream read failed session={} frame={}",
+                        "decoder stream read failed session={} frame={}: {error}",
                         inner.session_id, current_frame
                     );
-                    complete_pending_with_fallback(inner.clone()).await;
                     if let Some(mut old) = stream.take() {
                         old.shutdown().await;
                     }
+                    complete_pending_with_fallback(inner.clone()).await;
                     break;
                 }
             };
+            let frame = Arc::new(frame);
+            if frame_index < current_frame {
+                // Two timestamps rounded to the same index; keep the later frame for gaps.
+                previous_frame = Some(frame);
+                continue;
+            }
+            if frame_index > current_frame {
+                // The source has no frame for these indices; they show the one before.
+                let filler = previous_frame.clone().unwrap_or_else(|| frame.clone());
+                complete_pending_range(&inner, current_frame..frame_index, &filler, None).await;
+                current_frame = frame_index;
+            }
 
-            let should_complete = {
-                let mut pending = inner.pending_frames.lock().unwrap();
-                pending.remove(&current_frame)
-            };
-
-            if should_complete {
-                let future = {
-                    let frames = inner.frames.read().unwrap();
-                    frames.get(&current_frame).cloned()
-                };
+            // With a stride, one decoded frame stands in for every pending frame up to the
+            // next decoded one. Only the exact frame stays cached.
+            let window_end = current_frame.saturating_add(stride);
+            let completed = complete_pending_range(
+                &inner,
+                current_frame..window_end,
+                &frame,
+                Some(current_frame),
+            )
+            .await;
 
-                if let Some(future) = future {
-                    if !future.is_completed() {
-                        ENTIRE_CACHE_SIZE.fetch_add(frame.len(), Ordering::Relaxed);
-                        future.complete(Arc::new(frame)).await;
-                    }
-                }
+            if reverse && !completed.contains(&current_frame) {
+                store_decoded_frame(&inner, current_frame, frame.clone()).await;
             }
 
-            current_frame = current_frame.saturating_add(1);
+            previous_frame = Some(frame);
+            current_frame = window_end;
         }
     }
 
@@ -598,7 +1743,89 @@ async fn run_stream_loop(inner: Arc<Inner>) {
     }
 }
 
+fn playback_stride(rate: f64) -> u32 {
+    let speed = rate.abs();
+    if speed >= HIGH_SPEED_RATE {
+        (speed.floor() as u32).min(MAX_PLAYBACK_STRIDE)
+    } else {
+        1
+    }
+}
+
+fn reverse_chunk_start(target_frame: u32, stride: u32) -> u32 {
+    let span = (REVERSE_CHUNK_FRAMES - 1).saturating_mul(stride);
+    match target_frame.checked_sub(span) {
+        Some(start) => start,
+        // Keep the chunk on the same grid as the target.
+        None => target_frame % stride,
+    }
+}
+
+/// Complete the pending frames in `range` with `frame`. Only `exact` is cached; the others
+/// get the frame as a stand-in. Returns the frames completed.
+async fn complete_pending_range(
+    inner: &Inner,
+    range: Range<u32>,
+    frame: &Arc<Vec<u8>>,
+    exact: Option<u32>,
+) -> Vec<u32> {
+    let completed = {
+        let mut pending = inner.pending_frames.lock().unwrap();
+        let completed = pending.range(range).cloned().collect::<Vec<_>>();
+        for frame_index in &completed {
+            pending.remove(frame_index);
+        }
+        completed
+    };
+
+    for frame_index in completed.iter().copied() {
+        let future = {
+            let frames = inner.frames.read().unwrap();
+            frames.get(&frame_index).cloned()
+        };
+
+        let Some(future) = future else {
+            continue;
+        };
+        if future.is_completed() {
+            continue;
+        }
+
+        if Some(frame_index) == exact {
+            inner.charge(frame.len());
+            inner.persist(frame_index, frame);
+            future.complete(frame.clone()).await;
+        } else {
+            future.complete(frame.clone()).await;
+            inner.frames.write().unwrap().remove(&frame_index);
+        }
+    }
+    completed
+}
+
+/// Cache a frame nobody asked for yet (e.g. while decoding a reverse chunk).
+async fn store_decoded_frame(inner: &Inner, frame_index: u32, frame: Arc<Vec<u8>>) {
+    let future = {
+        let mut frames = inner.frames.write().unwrap();
+        frames
+            .entry(frame_index)
+            .or_insert_with(SharedManualFuture::new)
+            .clone()
+    };
+    if future.is_completed() {
+        return;
+    }
+
+    inner.charge(frame.len());
+    inner.persist(frame_index, &frame);
+    future.complete(frame).await;
+}
+
 async fn complete_pending_with_fallback(inner: Arc<Inner>) {
+    let has_alpha = source_codec(&inner.probe_path(), &inner.stream)
+        .await
+        .is_some_and(|codec| codec.has_alpha);
+    let can_extract = extract_matches_decoder(&inner).await;
     let pending = {
         let pending = inner.pending_frames.lock().unwrap();
         pending.iter().cloned().collect::<Vec<_>>()
@@ -639,24 +1866,85 @@ async fn complete_pending_with_fallback(inner: Arc<Inner>) {
                 found
             };
             if let Some((_, previous_frame)) = previous_frame {
-                ENTIRE_CACHE_SIZE.fetch_add(previous_frame.len(), Ordering::Relaxed);
+                metrics::record_fallback(Fallback::PreviousFrame);
+                inner.charge(previous_frame.len());
                 future.complete(previous_frame).await;
                 continue;
             }
 
-            let frame = hw_decoder::extract_frame_hw_rgba(
-                &inner.path,
-                frame_index as _,
-                inner.width,
-                inner.height,
-            )
-            .unwrap_or_else(|_| generate_empty_frame(inner.width, inner.height));
-            ENTIRE_CACHE_SIZE.fetch_add(frame.len(), Ordering::Relaxed);
+            // Alpha sources fall back to a transparent frame instead of an opaque one.
+            let frame = match inner.format {
+                _ if has_alpha => {
+                    metrics::record_fallback(Fallback::Placeholder);
+                    inner.format.blank_frame(inner.width, inner.height, false)
+                }
+                PixelFormat::Rgba if can_extract => {
+                    match extract_fallback_frame(&inner, frame_index).await {
+                        Ok(frame) => {
+                            metrics::record_fallback(Fallback::Extract);
+                            frame
+                        }
+                        Err(error) => {
+                            warn!("fallback extract of frame {frame_index} failed: {error}");
+                            metrics::record_fallback(Fallback::Placeholder);
+                            inner.empty_frame()
+                        }
+                    }
+                }
+                _ => {
+                    metrics::record_fallback(Fallback::Placeholder);
+                    inner.empty_frame()
+                }
+            };
+            inner.charge(frame.len());
             future.complete(Arc::new(frame)).await;
         }
     }
 }
 
+/// Whether a one-shot extract gives the frames this decoder would. It only scales, so it
+/// must not be used when the decoder filters, converts colors, rotates, unsqueezes or
+/// deinterlaces, or reads anything but the first video stream of a single file.
+async fn extract_matches_decoder(inner: &Inner) -> bool {
+    if inner.sequence.is_some()
+        || inner.stream != DEFAULT_VIDEO_STREAM
+        || !inner.filters.is_empty()
+        || inner.color != ColorOptions::default()
+    {
+        return false;
+    }
+    let upright = source_geometry(&inner.path, &inner.stream)
+        .await
+        .is_some_and(|geometry| {
+            let (num, den) = geometry.sample_aspect_ratio;
+            geometry.rotation == 0 && num == den
+        });
+    let field_order = source_field_order(&inner.path, &inner.stream)
+        .await
+        .unwrap_or_default();
+    upright && inner.deinterlace.filter(field_order).is_none()
+}
+
+/// Extract one frame with a separate ffmpeg run, under its own slot. The caller must not
+/// hold one, or a pool of one never grants it.
+async fn extract_fallback_frame(inner: &Inner, frame_index: u32) -> Result<Vec<u8>, String> {
+    let _slot = acquire_slot(inner).await?;
+    let (path, width, height) = (inner.path.clone(), inner.width, inner.height);
+    tokio::task::spawn_blocking(move || {
+        hw_decoder::extract_frame_hw_rgba(&path, frame_index as usize, width, height)
+    })
+    .await
+    .map_err(|error| format!("extract task failed: {error}"))?
+}
+
+/// Placeholder for frames that could not be decoded: solid red for RGBA, black otherwise.
+pub fn placeholder_frame(format: PixelFormat, width: u32, height: u32) -> Vec<u8> {
+    match format {
+        PixelFormat::Rgba => generate_empty_frame(width, height),
+        format => format.blank_frame(width, height, false),
+    }
+}
+
 pub fn generate_empty_frame(width: u32, height: u32) -> Vec<u8> {
     let mut buf = vec![0u8; (width * height * 4) as usize];
 
@@ -678,3 +1966,41 @@ pub fn generate_empty_frame(width: u32, height: u32) -> Vec<u8> {
 
     buf
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_range_policy_passes_frames_in_range() {
+        for policy in [
+            OutOfRangePolicy::Hold,
+            OutOfRangePolicy::Loop,
+            OutOfRangePolicy::PingPong,
+            OutOfRangePolicy::Transparent,
+        ] {
+            assert_eq!(policy.resolve(0, 4), Some(0));
+            assert_eq!(policy.resolve(3, 4), Some(3));
+        }
+    }
+
+    #[test]
+    fn out_of_range_policy_maps_frames_past_the_end() {
+        assert_eq!(OutOfRangePolicy::Hold.resolve(10, 4), Some(3));
+        assert_eq!(OutOfRangePolicy::Loop.resolve(4, 4), Some(0));
+        assert_eq!(OutOfRangePolicy::Loop.resolve(9, 4), Some(1));
+        assert_eq!(OutOfRangePolicy::Transparent.resolve(4, 4), None);
+
+        let ping_pong = (4..11)
+            .map(|frame| OutOfRangePolicy::PingPong.resolve(frame, 4).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ping_pong, [2, 1, 0, 1, 2, 3, 2]);
+        assert_eq!(OutOfRangePolicy::PingPong.resolve(7, 1), Some(0));
+    }
+
+    #[test]
+    fn out_of_range_policy_without_frames_is_transparent() {
+        assert_eq!(OutOfRangePolicy::Hold.resolve(0, 0), None);
+        assert_eq!(OutOfRangePolicy::Loop.resolve(5, 0), None);
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, atomic::Ordering},
+};
+
+use serde::Serialize;
+
+use crate::decoder::{ENTIRE_CACHE_SIZE, pixel_format::PixelFormat};
+
+/// Bytes cached per `/ws` session.
+static SESSION_USAGE: LazyLock<Mutex<HashMap<u64, usize>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+/// Quota of individual sessions, overriding [`DEFAULT_SESSION_QUOTA`].
+static SESSION_QUOTAS: LazyLock<Mutex<HashMap<u64, usize>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+/// Quota of every other session. `None` leaves sessions bound by the global budget only.
+static DEFAULT_SESSION_QUOTA: Mutex<Option<usize>> = Mutex::new(None);
+
+/// Smallest quota accepted, so a session can always hold a few frames.
+const MIN_SESSION_QUOTA: usize = 16 * 1024 * 1024;
+
+pub(super) fn charge(session_id: u64, bytes: usize) {
+    ENTIRE_CACHE_SIZE.fetch_add(bytes, Ordering::Relaxed);
+    *SESSION_USAGE.lock().unwrap().entry(session_id).or_insert(0) += bytes;
+}
+
+pub(super) fn release(session_id: u64, bytes: usize) {
+    let _ = ENTIRE_CACHE_SIZE.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+        Some(used.saturating_sub(bytes))
+    });
+    let mut usage = SESSION_USAGE.lock().unwrap();
+    if let Some(used) = usage.get_mut(&session_id) {
+        *used = used.saturating_sub(bytes);
+        if *used == 0 {
+            usage.remove(&session_id);
+        }
+    }
+}
+
+/// Forget everything about a session once its client is gone.
+pub(super) fn forget_session(session_id: u64) {
+    SESSION_USAGE.lock().unwrap().remove(&session_id);
+    SESSION_QUOTAS.lock().unwrap().remove(&session_id);
+}
+
+pub(super) fn reset() {
+    ENTIRE_CACHE_SIZE.store(0, Ordering::Relaxed);
+    SESSION_USAGE.lock().unwrap().clear();
+}
+
+/// Limit how much one session (or, with `None`, every session) may cache.
+/// A `bytes` of `None` removes the quota.
+pub fn set_session_quota(session_id: Option<u64>, bytes: Option<usize>) {
+    let bytes = bytes.map(|bytes| bytes.max(MIN_SESSION_QUOTA));
+    match session_id {
+        Some(session_id) => {
+            let mut quotas = SESSION_QUOTAS.lock().unwrap();
+            match bytes {
+                Some(bytes) => quotas.insert(session_id, bytes),
+                None => quotas.remove(&session_id),
+            };
+        }
+        None => *DEFAULT_SESSION_QUOTA.lock().unwrap() = bytes,
+    }
+}
+
+pub fn session_quota(session_id: u64) -> Option<usize> {
+    SESSION_QUOTAS
+        .lock()
+        .unwrap()
+        .get(&session_id)
+        .copied()
+        .or(*DEFAULT_SESSION_QUOTA.lock().unwrap())
+}
+
+pub fn default_session_quota() -> Option<usize> {
+    *DEFAULT_SESSION_QUOTA.lock().unwrap()
+}
+
+pub(super) fn session_over_quota(session_id: u64) -> bool {
+    let Some(quota) = session_quota(session_id) else {
+        return false;
+    };
+    let used = SESSION_USAGE
+        .lock()
+        .unwrap()
+        .get(&session_id)
+        .copied()
+        .unwrap_or(0);
+    used >= quota
+}
+
+/// Memory held by one decoder, as reported by `GET /cache`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DecoderUsage {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    #[serde(rename = "sessionId")]
+    pub session_id: u64,
+    pub frames: usize,
+    pub bytes: usize,
+}
+
+/// Memory held by the decoders of one `/ws` session.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionUsage {
+    #[serde(rename = "sessionId")]
+    pub session_id: u64,
+    pub decoders: usize,
+    pub bytes: usize,
+    #[serde(rename = "quotaBytes")]
+    pub quota_bytes: Option<usize>,
+}
+
+/// Group decoder usage by session.
+pub fn sessions(decoders: &[DecoderUsage]) -> Vec<SessionUsage> {
+    let mut sessions: Vec<SessionUsage> = Vec::new();
+    for decoder in decoders {
+        match sessions
+            .iter_mut()
+            .find(|session| session.session_id == decoder.session_id)
+        {
+            Some(session) => {
+                session.decoders += 1;
+                session.bytes += decoder.bytes;
+            }
+            None => sessions.push(SessionUsage {
+                session_id: decoder.session_id,
+                decoders: 1,
+                bytes: decoder.bytes,
+                quota_bytes: session_quota(decoder.session_id),
+            }),
+        }
+    }
+    sessions.sort_by_key(|session| session.session_id);
+    sessions
+}
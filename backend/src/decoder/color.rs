@@ -0,0 +1,244 @@
+use std::sync::Once;
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    decoder::pixel_format::{ColorMatrix, PixelFormat, is_full_range},
+    ffmpeg::{VideoColorInfo, capabilities::capabilities},
+};
+
+/// Operator used to bring HDR (PQ/HLG) sources into SDR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneMapOperator {
+    None,
+    Clip,
+    Linear,
+    Gamma,
+    Reinhard,
+    #[default]
+    Hable,
+    Mobius,
+}
+
+impl ToneMapOperator {
+    fn ffmpeg_name(self) -> &'static str {
+        match self {
+            ToneMapOperator::None => "none",
+            ToneMapOperator::Clip => "clip",
+            ToneMapOperator::Linear => "linear",
+            ToneMapOperator::Gamma => "gamma",
+            ToneMapOperator::Reinhard => "reinhard",
+            ToneMapOperator::Hable => "hable",
+            ToneMapOperator::Mobius => "mobius",
+        }
+    }
+}
+
+/// Per-request override of the color description found in the file.
+/// Values use ffprobe's names (e.g. `bt709`, `smpte170m`, `pc`, `arib-std-b67`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize)]
+pub struct ColorOptions {
+    #[serde(rename = "colorSpace")]
+    pub color_space: Option<String>,
+    #[serde(rename = "colorRange")]
+    pub color_range: Option<String>,
+    #[serde(rename = "colorPrimaries")]
+    pub color_primaries: Option<String>,
+    #[serde(rename = "colorTransfer")]
+    pub color_transfer: Option<String>,
+    #[serde(rename = "toneMap")]
+    pub tone_map: Option<ToneMapOperator>,
+}
+
+const COLOR_SPACES: &[&str] = &[
+    "bt709",
+    "smpte170m",
+    "bt470bg",
+    "fcc",
+    "smpte240m",
+    "bt2020nc",
+    "bt2020c",
+];
+const COLOR_RANGES: &[&str] = &["tv", "mpeg", "pc", "jpeg"];
+const COLOR_PRIMARIES: &[&str] = &[
+    "bt709",
+    "smpte170m",
+    "bt470bg",
+    "bt470m",
+    "smpte240m",
+    "bt2020",
+];
+const COLOR_TRANSFERS: &[&str] = &[
+    "bt709",
+    "smpte170m",
+    "bt470bg",
+    "gamma22",
+    "gamma28",
+    "iec61966-2-1",
+    "smpte2084",
+    "arib-std-b67",
+    "bt2020-10",
+    "bt2020-12",
+    "linear",
+];
+
+fn pick(value: Option<&String>, allowed: &[&str], probed: Option<String>) -> Option<String> {
+    match value {
+        // Only known names may reach the filter graph.
+        Some(value) if allowed.contains(&value.as_str()) => Some(value.clone()),
+        _ => probed,
+    }
+}
+
+impl ColorOptions {
+    /// The probed description with this override applied.
+    pub fn apply(&self, probed: &VideoColorInfo) -> VideoColorInfo {
+        VideoColorInfo {
+            pix_fmt: probed.pix_fmt.clone(),
+            color_space: pick(
+                self.color_space.as_ref(),
+                COLOR_SPACES,
+                probed.color_space.clone(),
+            ),
+            color_range: pick(
+                self.color_range.as_ref(),
+                COLOR_RANGES,
+                probed.color_range.clone(),
+            ),
+            color_primaries: pick(
+                self.color_primaries.as_ref(),
+                COLOR_PRIMARIES,
+                probed.color_primaries.clone(),
+            ),
+            color_transfer: pick(
+                self.color_transfer.as_ref(),
+                COLOR_TRANSFERS,
+                probed.color_transfer.clone(),
+            ),
+        }
+    }
+}
+
+pub fn is_hdr(info: &VideoColorInfo) -> bool {
+    matches!(
+        info.color_transfer.as_deref(),
+        Some("smpte2084" | "arib-std-b67")
+    )
+}
+
+fn is_wide_gamut(info: &VideoColorInfo) -> bool {
+    info.color_primaries.as_deref() == Some("bt2020")
+}
+
+fn swscale_matrix(matrix: ColorMatrix) -> &'static str {
+    match matrix {
+        ColorMatrix::Bt601 => "bt601",
+        ColorMatrix::Bt709 => "bt709",
+        ColorMatrix::Bt2020 => "bt2020",
+    }
+}
+
+fn zimg_matrix(matrix: ColorMatrix) -> &'static str {
+    match matrix {
+        ColorMatrix::Bt601 => "470bg",
+        ColorMatrix::Bt709 => "709",
+        ColorMatrix::Bt2020 => "2020_ncl",
+    }
+}
+
+fn zimg_transfer(transfer: Option<&str>) -> &'static str {
+    match transfer {
+        Some("smpte2084") => "smpte2084",
+        Some("arib-std-b67") => "arib-std-b67",
+        Some("smpte170m" | "bt470bg") => "601",
+        Some("linear") => "linear",
+        Some("iec61966-2-1") => "iec61966-2-1",
+        _ => "709",
+    }
+}
+
+fn zimg_primaries(primaries: Option<&str>) -> &'static str {
+    match primaries {
+        Some("bt2020") => "2020",
+        Some("smpte170m") => "170m",
+        Some("bt470bg") => "470bg",
+        Some("bt470m") => "470m",
+        Some("smpte240m") => "240m",
+        _ => "709",
+    }
+}
+
+/// Whether ffmpeg has the `zscale` filter that HDR and wide-gamut conversion is built on.
+pub async fn zscale_available() -> bool {
+    tokio::task::spawn_blocking(|| {
+        capabilities()
+            .filters
+            .iter()
+            .any(|filter| filter == "zscale")
+    })
+    .await
+    .unwrap_or(false)
+}
+
+static NO_ZSCALE_WARNING: Once = Once::new();
+
+/// Filter chain that resizes a frame to `width`x`height` and converts it from the source
+/// color description to BT.709 (limited range when `output` is YUV).
+///
+/// `height_hint` is the source height, used to guess the matrix of untagged files. Without
+/// `zscale`, HDR and BT.2020 sources only get their matrix converted by swscale.
+pub fn scale_filter(
+    info: &VideoColorInfo,
+    options: &ColorOptions,
+    width: u32,
+    height: u32,
+    height_hint: u32,
+    output: PixelFormat,
+    zscale: bool,
+) -> String {
+    let info = options.apply(info);
+    let matrix = ColorMatrix::from_color_info(&info, height_hint);
+    let full_range = is_full_range(&info);
+    let in_range = if full_range { "pc" } else { "tv" };
+
+    let needs_zscale = is_hdr(&info) || is_wide_gamut(&info);
+    if needs_zscale && !zscale {
+        NO_ZSCALE_WARNING.call_once(|| {
+            warn!("ffmpeg has no zscale filter, HDR sources are converted without tone mapping")
+        });
+    } else if needs_zscale {
+        let tone_map = options.tone_map.unwrap_or_default();
+        // The alpha variants keep the alpha plane of keyed sources through the conversion.
+        let (linear, yuv) = if output.has_alpha() {
+            ("gbrapf32le", "yuva420p")
+        } else {
+            ("gbrpf32le", "yuv420p")
+        };
+        let mut chain = format!(
+            "scale={width}:{height},zscale=min={}:rin={}:tin={}:pin={}:t=linear:npl=100,format={linear},zscale=p=709",
+            zimg_matrix(matrix),
+            if full_range { "full" } else { "limited" },
+            zimg_transfer(info.color_transfer.as_deref()),
+            zimg_primaries(info.color_primaries.as_deref()),
+        );
+        if is_hdr(&info) {
+            chain.push_str(&format!(
+                ",tonemap=tonemap={}:desat=0",
+                tone_map.ffmpeg_name()
+            ));
+        }
+        chain.push_str(&format!(",zscale=t=709:m=709:r=limited,format={yuv}"));
+        return chain;
+    }
+
+    let mut chain = format!(
+        "scale={width}:{height}:in_color_matrix={}:in_range={in_range}",
+        swscale_matrix(matrix)
+    );
+    if output.is_yuv() {
+        chain.push_str(":out_color_matrix=bt709:out_range=tv");
+    }
+    chain
+}
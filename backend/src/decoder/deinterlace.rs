@@ -0,0 +1,69 @@
+use serde::Deserialize;
+
+use crate::ffmpeg::FieldOrder;
+
+/// Deinterlacing filter used by the decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Deinterlacer {
+    /// `bwdif` when the source is flagged as interlaced, nothing otherwise.
+    #[default]
+    Auto,
+    Off,
+    Bwdif,
+    Yadif,
+}
+
+/// Output rate of the deinterlacer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldRate {
+    /// One frame per interlaced frame.
+    #[default]
+    Frame,
+    /// One frame per field, doubling the frame rate and frame count.
+    Field,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+pub struct DeinterlaceOptions {
+    pub method: Option<Deinterlacer>,
+    pub rate: Option<FieldRate>,
+}
+
+impl DeinterlaceOptions {
+    fn deinterlacer(&self, field_order: FieldOrder) -> Option<&'static str> {
+        match self.method.unwrap_or_default() {
+            Deinterlacer::Auto if field_order.is_interlaced() => Some("bwdif"),
+            Deinterlacer::Auto | Deinterlacer::Off => None,
+            Deinterlacer::Bwdif => Some("bwdif"),
+            Deinterlacer::Yadif => Some("yadif"),
+        }
+    }
+
+    /// Deinterlace filter for a source with `field_order`, if any applies.
+    pub fn filter(&self, field_order: FieldOrder) -> Option<String> {
+        let name = self.deinterlacer(field_order)?;
+        let mode = match self.rate.unwrap_or_default() {
+            FieldRate::Frame => "send_frame",
+            FieldRate::Field => "send_field",
+        };
+        let parity = match field_order {
+            FieldOrder::TopFirst => "tff",
+            FieldOrder::BottomFirst => "bff",
+            FieldOrder::Progressive => "auto",
+        };
+        Some(format!("{name}=mode={mode}:parity={parity}:deint=all"))
+    }
+
+    /// Factor applied to the source frame rate and frame count.
+    pub fn rate_multiplier(&self, field_order: FieldOrder) -> u32 {
+        match (
+            self.deinterlacer(field_order),
+            self.rate.unwrap_or_default(),
+        ) {
+            (Some(_), FieldRate::Field) => 2,
+            _ => 1,
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    fs,
+    hash::Hasher,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::SystemTime,
+};
+
+use serde::Serialize;
+use tracing::warn;
+
+const MIB: u64 = 1024 * 1024;
+/// Frames being written at once. Further frames are not persisted while the disk catches up.
+const MAX_PENDING_WRITES: usize = 4;
+/// Eviction trims the cache to this fraction of its limit so it does not run on every write.
+const EVICT_TARGET: f64 = 0.9;
+/// Bumped whenever the file layout changes, so stale entries are never read.
+const FORMAT_VERSION: u32 = 2;
+
+/// Second cache tier: decoded frames stored as LZ4 files, one directory per source.
+#[derive(Debug, Clone)]
+struct DiskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+static CACHE: LazyLock<Mutex<Option<DiskCache>>> = LazyLock::new(|| {
+    let cache = from_env();
+    if let Some(cache) = &cache {
+        USED_BYTES.store(directory_size(&cache.dir), Ordering::Relaxed);
+    }
+    Mutex::new(cache)
+});
+static USED_BYTES: AtomicU64 = AtomicU64::new(0);
+static PENDING_WRITES: AtomicUsize = AtomicUsize::new(0);
+static EVICTING: AtomicBool = AtomicBool::new(false);
+/// Makes temporary file names unique within this process.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// 64-bit FNV-1a. Keys outlive the process, so they cannot use the std hasher, whose output
+/// may change between Rust releases.
+pub(super) struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn default_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("framescript")
+        .join("frames")
+}
+
+/// `FRAMESCRIPT_DISK_CACHE_MIB` enables the cache; `FRAMESCRIPT_DISK_CACHE_DIR` moves it.
+fn from_env() -> Option<DiskCache> {
+    let mib = std::env::var("FRAMESCRIPT_DISK_CACHE_MIB")
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|mib| *mib > 0)?;
+    let dir = std::env::var("FRAMESCRIPT_DISK_CACHE_DIR")
+        .ok()
+        .filter(|dir| !dir.trim().is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(default_dir);
+    Some(DiskCache {
+        dir,
+        max_bytes: mib * MIB,
+    })
+}
+
+fn current() -> Option<DiskCache> {
+    CACHE.lock().unwrap().clone()
+}
+
+/// Enable the disk cache with a limit of `max_bytes` (0 disables it), optionally moving it
+/// to `dir`. Blocks while the existing files are measured.
+pub fn configure(dir: Option<PathBuf>, max_bytes: u64) -> Result<(), String> {
+    let mut cache = CACHE.lock().unwrap();
+    if max_bytes == 0 {
+        *cache = None;
+        return Ok(());
+    }
+
+    let dir = dir
+        .or_else(|| cache.as_ref().map(|cache| cache.dir.clone()))
+        .unwrap_or_else(default_dir);
+    fs::create_dir_all(&dir)
+        .map_err(|error| format!("failed to create {}: {error}", dir.display()))?;
+    USED_BYTES.store(directory_size(&dir), Ordering::Relaxed);
+    *cache = Some(DiskCache {
+        dir,
+        max_bytes: max_bytes.max(MIB),
+    });
+    drop(cache);
+    evict_if_needed();
+    Ok(())
+}
+
+/// Delete every cached frame. Blocking.
+pub fn purge() -> u64 {
+    let Some(cache) = current() else {
+        return 0;
+    };
+    let freed = directory_size(&cache.dir);
+    if let Ok(entries) = fs::read_dir(&cache.dir) {
+        for entry in entries.flatten() {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+    USED_BYTES.store(0, Ordering::Relaxed);
+    freed
+}
+
+/// State of the disk cache, as reported by `GET /cache`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskCacheStatus {
+    pub enabled: bool,
+    pub dir: Option<String>,
+    #[serde(rename = "maxBytes")]
+    pub max_bytes: u64,
+    #[serde(rename = "usedBytes")]
+    pub used_bytes: u64,
+}
+
+pub fn status() -> DiskCacheStatus {
+    let cache = current();
+    DiskCacheStatus {
+        enabled: cache.is_some(),
+        dir: cache
+            .as_ref()
+            .map(|cache| cache.dir.to_string_lossy().into_owned()),
+        max_bytes: cache.as_ref().map_or(0, |cache| cache.max_bytes),
+        used_bytes: USED_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+pub(super) fn is_enabled() -> bool {
+    CACHE.lock().unwrap().is_some()
+}
+
+fn frame_path(dir: &Path, source_key: u64, frame_index: u32) -> PathBuf {
+    dir.join(format!("{source_key:016x}-v{FORMAT_VERSION}"))
+        .join(format!("{frame_index:08}.lz4"))
+}
+
+/// Read a frame of `expected_len` bytes. Blocking.
+pub(super) fn load(source_key: u64, frame_index: u32, expected_len: usize) -> Option<Vec<u8>> {
+    let cache = current()?;
+    let path = frame_path(&cache.dir, source_key, frame_index);
+    let data = fs::read(&path).ok()?;
+
+    // The first four bytes hold the decompressed size; check it before allocating.
+    let size = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    if size != expected_len {
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+    let frame = match lz4_flex::decompress_size_prepended(&data) {
+        Ok(frame) if frame.len() == expected_len => frame,
+        _ => {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+    };
+
+    // Keep recently used frames at the back of the eviction order.
+    if let Ok(file) = fs::File::options().write(true).open(&path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+    Some(frame)
+}
+
+/// Persist a frame in the background. Dropped when too many writes are already queued.
+pub(super) fn store(source_key: u64, frame_index: u32, frame: Arc<Vec<u8>>) {
+    let Some(cache) = current() else {
+        return;
+    };
+    if PENDING_WRITES.fetch_add(1, Ordering::Relaxed) >= MAX_PENDING_WRITES {
+        PENDING_WRITES.fetch_sub(1, Ordering::Relaxed);
+        return;
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let path = frame_path(&cache.dir, source_key, frame_index);
+        if let Err(error) = write_frame(&path, &frame) {
+            warn!("disk cache write failed for {}: {error}", path.display());
+        }
+        PENDING_WRITES.fetch_sub(1, Ordering::Relaxed);
+        evict_if_needed();
+    });
+}
+
+fn write_frame(path: &Path, frame: &[u8]) -> std::io::Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let compressed = lz4_flex::compress_prepend_size(frame);
+    // Write under a temporary name so readers never see a partial file. Other writers of
+    // the same frame, in this process or another sharing the directory, use their own.
+    let temp = path.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    if let Err(error) = fs::write(&temp, &compressed).and_then(|_| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(error);
+    }
+    USED_BYTES.fetch_add(compressed.len() as u64, Ordering::Relaxed);
+    Ok(())
+}
+
+fn directory_size(dir: &Path) -> u64 {
+    cached_files(dir).iter().map(|(_, size, _)| size).sum()
+}
+
+/// Every frame file with its size and last use.
+fn cached_files(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let mut files = Vec::new();
+    let Ok(sources) = fs::read_dir(dir) else {
+        return files;
+    };
+    for source in sources.flatten() {
+        let Ok(frames) = fs::read_dir(source.path()) else {
+            continue;
+        };
+        for frame in frames.flatten() {
+            let Ok(metadata) = frame.metadata() else {
+                continue;
+            };
+            if metadata.is_file() {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.push((frame.path(), metadata.len(), modified));
+            }
+        }
+    }
+    files
+}
+
+/// Delete the least recently used frames once the cache outgrows its limit. Blocking.
+fn evict_if_needed() {
+    let Some(cache) = current() else {
+        return;
+    };
+    if USED_BYTES.load(Ordering::Relaxed) <= cache.max_bytes
+        || EVICTING.swap(true, Ordering::Relaxed)
+    {
+        return;
+    }
+
+    let mut files = cached_files(&cache.dir);
+    files.sort_by_key(|(_, _, modified)| *modified);
+    let mut used: u64 = files.iter().map(|(_, size, _)| size).sum();
+    let target = (cache.max_bytes as f64 * EVICT_TARGET) as u64;
+    for (path, size, _) in files {
+        if used <= target {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            used = used.saturating_sub(size);
+            if let Some(parent) = path.parent() {
+                // Only succeeds once the source has no frames left.
+                let _ = fs::remove_dir(parent);
+            }
+        }
+    }
+    USED_BYTES.store(used, Ordering::Relaxed);
+    EVICTING.store(false, Ordering::Relaxed);
+}
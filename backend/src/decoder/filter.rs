@@ -0,0 +1,304 @@
+use serde::Deserialize;
+
+use crate::{ffmpeg::VideoGeometry, util::KeyFloat};
+
+/// A filter applied by the decoder before frames are cached.
+///
+/// Crop rectangles are in displayed (rotated) source pixels. Keying filters produce real
+/// alpha, which is kept in RGBA and `yuva420p` output.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum VideoFilter {
+    Chromakey {
+        color: String,
+        similarity: KeyFloat,
+        #[serde(default)]
+        blend: KeyFloat,
+    },
+    Colorkey {
+        color: String,
+        similarity: KeyFloat,
+        #[serde(default)]
+        blend: KeyFloat,
+    },
+    /// Apply a 3D LUT (`.cube`, `.3dl`, ...) from disk.
+    Lut3d { path: String },
+    Crop {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    Eq {
+        brightness: Option<KeyFloat>,
+        contrast: Option<KeyFloat>,
+        saturation: Option<KeyFloat>,
+    },
+}
+
+/// Escape a value for use as a filter option inside a filtergraph string.
+fn escape_filter_value(value: &str) -> String {
+    let mut option = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '\'' | ':') {
+            option.push('\\');
+        }
+        option.push(c);
+    }
+
+    let mut graph = String::with_capacity(option.len());
+    for c in option.chars() {
+        if matches!(c, '\\' | '\'' | '[' | ']' | ',' | ';') {
+            graph.push('\\');
+        }
+        graph.push(c);
+    }
+    graph
+}
+
+/// `#RRGGBB`, `0xRRGGBB[AA]` or a color name, as understood by ffmpeg.
+fn sanitize_color(color: &str) -> Option<String> {
+    let color = color.trim();
+    let color = match color.strip_prefix('#') {
+        Some(hex) => format!("0x{hex}"),
+        None => color.to_string(),
+    };
+    if !color.is_empty() && color.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(color)
+    } else {
+        None
+    }
+}
+
+/// Crop rectangle of the last crop filter, clamped to the displayed source size.
+fn crop_rect(filters: &[VideoFilter], display: (u32, u32)) -> Option<(u32, u32, u32, u32)> {
+    filters.iter().rev().find_map(|filter| match *filter {
+        VideoFilter::Crop {
+            x,
+            y,
+            width,
+            height,
+        } => {
+            let x = x.min(display.0.saturating_sub(1));
+            let y = y.min(display.1.saturating_sub(1));
+            let width = width.clamp(1, display.0 - x);
+            let height = height.clamp(1, display.1 - y);
+            Some((x, y, width, height))
+        }
+        _ => None,
+    })
+}
+
+/// Displayed size of a source once its crop filter is applied.
+pub fn cropped_dimensions(filters: &[VideoFilter], display: (u32, u32)) -> (u32, u32) {
+    match crop_rect(filters, display) {
+        Some((_, _, width, height)) => (width, height),
+        None => display,
+    }
+}
+
+/// `crop` filter running on coded frames, before scaling and rotation.
+pub fn crop_filter(filters: &[VideoFilter], geometry: &VideoGeometry) -> Option<String> {
+    let (x, y, width, height) = crop_rect(filters, geometry.display_dimensions())?;
+    let (unrotated_width, unrotated_height) = geometry.unrotated_dimensions();
+
+    // Undo the display rotation (clockwise) to get a rectangle in unrotated pixels.
+    let (x, y, width, height) = match geometry.rotation {
+        90 => (y, unrotated_height.saturating_sub(x + width), height, width),
+        180 => (
+            unrotated_width.saturating_sub(x + width),
+            unrotated_height.saturating_sub(y + height),
+            width,
+            height,
+        ),
+        270 => (unrotated_width.saturating_sub(y + height), x, height, width),
+        _ => (x, y, width, height),
+    };
+
+    // Undo the sample aspect ratio stretch.
+    let (num, den) = geometry.sample_aspect_ratio;
+    let to_coded = |value: u32| (value as u64 * den as u64 / num.max(1) as u64) as u32;
+    let (x, width) = (to_coded(x), to_coded(width).max(1));
+
+    Some(format!("crop={width}:{height}:{x}:{y}"))
+}
+
+/// Filters running on decoded frames after scaling and rotation.
+pub fn post_filters(filters: &[VideoFilter]) -> String {
+    let mut chain = String::new();
+    for filter in filters {
+        let part = match filter {
+            VideoFilter::Chromakey {
+                color,
+                similarity,
+                blend,
+            } => sanitize_color(color).map(|color| {
+                format!(
+                    "chromakey=color={color}:similarity={:.6}:blend={:.6}",
+                    similarity.0.clamp(0.00001, 1.0),
+                    blend.0.clamp(0.0, 1.0)
+                )
+            }),
+            VideoFilter::Colorkey {
+                color,
+                similarity,
+                blend,
+            } => sanitize_color(color).map(|color| {
+                format!(
+                    "colorkey=color={color}:similarity={:.6}:blend={:.6}",
+                    similarity.0.clamp(0.00001, 1.0),
+                    blend.0.clamp(0.0, 1.0)
+                )
+            }),
+            VideoFilter::Lut3d { path } => {
+                Some(format!("lut3d=file={}", escape_filter_value(path)))
+            }
+            VideoFilter::Eq {
+                brightness,
+                contrast,
+                saturation,
+            } => Some(format!(
+                "eq=brightness={:.6}:contrast={:.6}:saturation={:.6}",
+                brightness.map_or(0.0, |value| value.0).clamp(-1.0, 1.0),
+                contrast.map_or(1.0, |value| value.0).clamp(-1000.0, 1000.0),
+                saturation.map_or(1.0, |value| value.0).clamp(0.0, 3.0)
+            )),
+            VideoFilter::Crop { .. } => None,
+        };
+
+        if let Some(part) = part {
+            chain.push(',');
+            chain.push_str(&part);
+        }
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters(json: &str) -> Vec<VideoFilter> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn geometry(width: u32, height: u32, rotation: u32) -> VideoGeometry {
+        VideoGeometry {
+            coded_width: width,
+            coded_height: height,
+            sample_aspect_ratio: (1, 1),
+            rotation,
+        }
+    }
+
+    #[test]
+    fn parses_filters_with_defaults() {
+        let parsed = filters(
+            r##"[
+                {"type": "chromakey", "color": "#00ff00", "similarity": 0.1},
+                {"type": "eq", "contrast": 1.5},
+                {"type": "crop", "x": 1, "y": 2, "width": 3, "height": 4}
+            ]"##,
+        );
+        assert_eq!(
+            parsed,
+            [
+                VideoFilter::Chromakey {
+                    color: "#00ff00".to_string(),
+                    similarity: KeyFloat(0.1),
+                    blend: KeyFloat(0.0),
+                },
+                VideoFilter::Eq {
+                    brightness: None,
+                    contrast: Some(KeyFloat(1.5)),
+                    saturation: None,
+                },
+                VideoFilter::Crop {
+                    x: 1,
+                    y: 2,
+                    width: 3,
+                    height: 4,
+                },
+            ]
+        );
+        assert!(serde_json::from_str::<VideoFilter>(r#"{"type": "blur"}"#).is_err());
+    }
+
+    #[test]
+    fn builds_post_filter_chain() {
+        let chain = post_filters(&filters(
+            r##"[
+                {"type": "chromakey", "color": "#00ff00", "similarity": 0.1},
+                {"type": "crop", "x": 0, "y": 0, "width": 2, "height": 2},
+                {"type": "eq", "brightness": 2.0}
+            ]"##,
+        ));
+        assert_eq!(
+            chain,
+            ",chromakey=color=0x00ff00:similarity=0.100000:blend=0.000000\
+             ,eq=brightness=1.000000:contrast=1.000000:saturation=1.000000"
+        );
+    }
+
+    #[test]
+    fn drops_keys_with_unsafe_colors() {
+        let chain = post_filters(&filters(
+            r#"[{"type": "colorkey", "color": "red,drawtext", "similarity": 0.5}]"#,
+        ));
+        assert_eq!(chain, "");
+    }
+
+    #[test]
+    fn escapes_lut_paths() {
+        let chain = post_filters(&[VideoFilter::Lut3d {
+            path: "/luts/a:b,c.cube".to_string(),
+        }]);
+        assert_eq!(chain, r",lut3d=file=/luts/a\\:b\,c.cube");
+    }
+
+    #[test]
+    fn clamps_crop_to_the_displayed_source() {
+        let crop = [VideoFilter::Crop {
+            x: 90,
+            y: 0,
+            width: 50,
+            height: 100,
+        }];
+        assert_eq!(cropped_dimensions(&crop, (100, 50)), (10, 50));
+        assert_eq!(cropped_dimensions(&[], (100, 50)), (100, 50));
+    }
+
+    #[test]
+    fn maps_crop_onto_coded_pixels() {
+        let crop = [VideoFilter::Crop {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 200,
+        }];
+        assert_eq!(
+            crop_filter(&crop, &geometry(1920, 1080, 0)).as_deref(),
+            Some("crop=100:200:0:0")
+        );
+        assert_eq!(
+            crop_filter(&crop, &geometry(1920, 1080, 90)).as_deref(),
+            Some("crop=200:100:0:980")
+        );
+
+        let anamorphic = VideoGeometry {
+            sample_aspect_ratio: (16, 15),
+            ..geometry(720, 576, 0)
+        };
+        let full_width = [VideoFilter::Crop {
+            x: 0,
+            y: 0,
+            width: 768,
+            height: 576,
+        }];
+        assert_eq!(
+            crop_filter(&full_width, &anamorphic).as_deref(),
+            Some("crop=720:576:0:0")
+        );
+        assert_eq!(crop_filter(&[], &anamorphic), None);
+    }
+}
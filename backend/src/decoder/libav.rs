@@ -0,0 +1,352 @@
+use std::{collections::VecDeque, sync::Once};
+
+use ffmpeg::{
+    codec, filter,
+    format::{self, Pixel},
+    frame, media,
+};
+use ffmpeg_next as ffmpeg;
+
+use crate::decoder::pixel_format::PixelFormat;
+
+static INIT: Once = Once::new();
+
+fn init() {
+    INIT.call_once(|| {
+        let _ = ffmpeg::init();
+        ffmpeg::log::set_level(ffmpeg::log::Level::Error);
+    });
+}
+
+fn error(context: &str, error: ffmpeg::Error) -> String {
+    format!("libav {context} failed: {error}")
+}
+
+fn pixel(format: PixelFormat) -> Pixel {
+    match format {
+        PixelFormat::Rgba => Pixel::RGBA,
+        PixelFormat::Yuv420p => Pixel::YUV420P,
+        PixelFormat::Nv12 => Pixel::NV12,
+        PixelFormat::Yuva420p => Pixel::YUVA420P,
+    }
+}
+
+/// The forms of `v:` stream specifier that `StreamSelector::specifier` produces.
+enum VideoSelector<'a> {
+    /// `v:1`: position among the video streams.
+    Position(usize),
+    /// `v:m:language:eng`: the first video stream with that language tag.
+    Language(&'a str),
+}
+
+impl<'a> VideoSelector<'a> {
+    /// Anything else is left to the ffmpeg CLI, which understands every specifier.
+    fn parse(stream: &'a str) -> Result<Self, String> {
+        let unsupported = || format!("stream specifier {stream} is not supported in-process");
+        let rest = stream.strip_prefix("v:").ok_or_else(unsupported)?;
+        if let Ok(position) = rest.parse() {
+            return Ok(Self::Position(position));
+        }
+        rest.strip_prefix("m:language:")
+            .map(Self::Language)
+            .ok_or_else(unsupported)
+    }
+}
+
+/// In-process counterpart of the ffmpeg CLI pipe: decodes one video stream, runs the same
+/// filter chain and hands out frames from `start_frame` on, each with the frame index its
+/// timestamp falls on.
+pub struct LibavStream {
+    input: format::context::Input,
+    stream_index: usize,
+    decoder: codec::decoder::Video,
+    graph: filter::Graph,
+    chain: String,
+    /// Frame index of every frame sitting in the filter graph, in order.
+    queued: VecDeque<u32>,
+    /// Last frame decoded before `start_frame`; it shows at `start_frame` when no frame
+    /// starts exactly there.
+    held: Option<frame::Video>,
+    stream_time_base: ffmpeg::Rational,
+    time_base: f64,
+    start_pts: i64,
+    fps: f64,
+    start_frame: u32,
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+    eof: bool,
+    flushed: bool,
+}
+
+impl LibavStream {
+    /// Open `path`, seek to the keyframe before `start_frame` and prepare `chain`, which
+    /// must turn decoded frames into `width`x`height` frames (it is followed by `format`).
+    #[allow(clippy::too_many_arguments)]
+    pub fn open(
+        path: &str,
+        stream: &str,
+        start_frame: u32,
+        fps: f64,
+        chain: &str,
+        format: PixelFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, String> {
+        init();
+
+        let selector = VideoSelector::parse(stream)?;
+        let input = format::input(path).map_err(|e| error("open", e))?;
+        let (stream_index, time_base, start_pts, parameters) = {
+            let mut videos = input
+                .streams()
+                .filter(|stream| stream.parameters().medium() == media::Type::Video);
+            let stream = match selector {
+                VideoSelector::Position(position) => videos.nth(position),
+                VideoSelector::Language(language) => {
+                    videos.find(|stream| stream.metadata().get("language") == Some(language))
+                }
+            }
+            .ok_or_else(|| format!("no video stream matches {stream}"))?;
+            let start_pts = match stream.start_time() {
+                ffmpeg::ffi::AV_NOPTS_VALUE => 0,
+                start => start,
+            };
+            (
+                stream.index(),
+                stream.time_base(),
+                start_pts,
+                stream.parameters(),
+            )
+        };
+
+        let mut context = codec::context::Context::from_parameters(parameters)
+            .map_err(|e| error("decoder setup", e))?;
+        context.set_threading(codec::threading::Config::kind(
+            codec::threading::Type::Frame,
+        ));
+        let decoder = context
+            .decoder()
+            .video()
+            .map_err(|e| error("decoder open", e))?;
+        let graph = build_graph(&decoder, time_base, chain, format)?;
+
+        let mut libav = Self {
+            input,
+            stream_index,
+            decoder,
+            graph,
+            chain: chain.to_string(),
+            queued: VecDeque::new(),
+            held: None,
+            stream_time_base: time_base,
+            time_base: f64::from(time_base),
+            start_pts,
+            fps: fps.max(1.0),
+            start_frame,
+            format,
+            width,
+            height,
+            eof: false,
+            flushed: false,
+        };
+        if start_frame > 0 {
+            libav.seek_input(start_frame)?;
+        }
+        Ok(libav)
+    }
+
+    /// Continue from `start_frame`, reusing the open input and decoder.
+    pub fn seek(&mut self, start_frame: u32) -> Result<(), String> {
+        self.seek_input(start_frame)?;
+        self.decoder.flush();
+        // The graph may still hold frames from before the seek.
+        self.graph = build_graph(
+            &self.decoder,
+            self.stream_time_base,
+            &self.chain,
+            self.format,
+        )?;
+        self.queued.clear();
+        self.held = None;
+        self.start_frame = start_frame;
+        self.eof = false;
+        self.flushed = false;
+        Ok(())
+    }
+
+    /// Seek the input to the keyframe before `start_frame`.
+    fn seek_input(&mut self, start_frame: u32) -> Result<(), String> {
+        let target_sec = self.start_pts as f64 * self.time_base + start_frame as f64 / self.fps;
+        let target = (target_sec * ffmpeg::ffi::AV_TIME_BASE as f64) as i64;
+        self.input
+            .seek(target, ..target)
+            .map_err(|e| error("seek", e))
+    }
+
+    /// Frame index of a decoded frame, from its timestamp.
+    fn frame_index(&self, pts: i64) -> u32 {
+        let seconds = (pts - self.start_pts) as f64 * self.time_base;
+        (seconds * self.fps).round().max(0.0) as u32
+    }
+
+    fn decode_next(&mut self) -> Result<Option<frame::Video>, String> {
+        loop {
+            let mut decoded = frame::Video::empty();
+            if self.decoder.receive_frame(&mut decoded).is_ok() {
+                return Ok(Some(decoded));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+
+            match self.input.packets().next() {
+                Some((stream, packet)) => {
+                    if stream.index() == self.stream_index {
+                        self.decoder
+                            .send_packet(&packet)
+                            .map_err(|e| error("decode", e))?;
+                    }
+                }
+                None => {
+                    self.decoder.send_eof().map_err(|e| error("decode", e))?;
+                    self.eof = true;
+                }
+            }
+        }
+    }
+
+    /// Next frame at or after `start_frame`, with its frame index. `None` at the end of the
+    /// stream. Indices increase but may skip where the source has no frame.
+    pub fn read_next(&mut self) -> Result<Option<(u32, Vec<u8>)>, String> {
+        loop {
+            let mut filtered = frame::Video::empty();
+            let received = self
+                .graph
+                .get("out")
+                .ok_or_else(|| "missing buffersink".to_string())?
+                .sink()
+                .frame(&mut filtered)
+                .is_ok();
+            if received {
+                let index = self.queued.pop_front().unwrap_or(self.start_frame);
+                return Ok(Some((index, self.copy_frame(&filtered))));
+            }
+            if self.flushed {
+                return Ok(None);
+            }
+
+            match self.decode_next()? {
+                Some(decoded) => {
+                    let Some(pts) = decoded.timestamp().or_else(|| decoded.pts()) else {
+                        continue;
+                    };
+                    // Seeking lands on a keyframe; drop what precedes the requested frame.
+                    let index = self.frame_index(pts);
+                    if index < self.start_frame {
+                        self.held = Some(decoded);
+                        continue;
+                    }
+                    if let Some(held) = self.held.take()
+                        && index > self.start_frame
+                    {
+                        self.filter(&held, self.start_frame)?;
+                    }
+                    self.filter(&decoded, index)?;
+                }
+                None => {
+                    if let Some(held) = self.held.take() {
+                        self.filter(&held, self.start_frame)?;
+                    }
+                    self.graph
+                        .get("in")
+                        .ok_or_else(|| "missing buffer source".to_string())?
+                        .source()
+                        .flush()
+                        .map_err(|e| error("filter", e))?;
+                    self.flushed = true;
+                }
+            }
+        }
+    }
+
+    /// Feed a decoded frame into the filter graph.
+    fn filter(&mut self, decoded: &frame::Video, index: u32) -> Result<(), String> {
+        self.graph
+            .get("in")
+            .ok_or_else(|| "missing buffer source".to_string())?
+            .source()
+            .add(decoded)
+            .map_err(|e| error("filter", e))?;
+        self.queued.push_back(index);
+        Ok(())
+    }
+
+    /// Copy the planes of `frame` into one tightly packed buffer.
+    fn copy_frame(&self, frame: &frame::Video) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(self.format.frame_size(self.width, self.height));
+        for (index, plane) in self
+            .format
+            .planes(self.width, self.height)
+            .into_iter()
+            .enumerate()
+        {
+            let row_len = plane.width as usize * plane.channels;
+            let stride = frame.stride(index);
+            let data = frame.data(index);
+            for row in 0..plane.height as usize {
+                let start = (row * stride).min(data.len());
+                let end = (start + row_len).min(data.len());
+                buffer.extend_from_slice(&data[start..end]);
+                buffer.resize(buffer.len() + row_len - (end - start), 0);
+            }
+        }
+        buffer
+    }
+}
+
+fn build_graph(
+    decoder: &codec::decoder::Video,
+    time_base: ffmpeg::Rational,
+    chain: &str,
+    format: PixelFormat,
+) -> Result<filter::Graph, String> {
+    let mut graph = filter::Graph::new();
+    let aspect = match decoder.aspect_ratio() {
+        aspect if aspect.numerator() > 0 && aspect.denominator() > 0 => aspect,
+        _ => ffmpeg::Rational::new(1, 1),
+    };
+    let args = format!(
+        "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
+        decoder.width(),
+        decoder.height(),
+        ffmpeg::ffi::AVPixelFormat::from(decoder.format()) as i32,
+        time_base.numerator(),
+        time_base.denominator(),
+        aspect.numerator(),
+        aspect.denominator(),
+    );
+
+    let buffer = filter::find("buffer").ok_or_else(|| "missing buffer filter".to_string())?;
+    let buffersink =
+        filter::find("buffersink").ok_or_else(|| "missing buffersink filter".to_string())?;
+    graph
+        .add(&buffer, "in", &args)
+        .map_err(|e| error("filter setup", e))?;
+    graph
+        .add(&buffersink, "out", "")
+        .map_err(|e| error("filter setup", e))?;
+    graph
+        .get("out")
+        .ok_or_else(|| "missing buffersink".to_string())?
+        .set_pixel_format(pixel(format));
+
+    let spec = format!("{},format={}", chain, format.ffmpeg_name());
+    graph
+        .output("in", 0)
+        .and_then(|parser| parser.input("out", 0))
+        .and_then(|parser| parser.parse(&spec))
+        .map_err(|e| error("filter parse", e))?;
+    graph.validate().map_err(|e| error("filter setup", e))?;
+    Ok(graph)
+}
@@ -0,0 +1,186 @@
+use std::{
+    fs,
+    sync::{
+        Once,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::decoder::{ENTIRE_CACHE_SIZE, MAX_CACHE_SIZE};
+
+const MIB: usize = 1024 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_MIN_CACHE_SIZE: usize = 256 * MIB;
+/// Memory left to the rest of the system (render workers, the studio) at the least.
+const MIN_RESERVE: usize = 1024 * MIB;
+/// Fraction of total memory left to the rest of the system on large machines.
+const RESERVE_FRACTION: f64 = 0.1;
+
+static MIN_CACHE_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MIN_CACHE_SIZE);
+static ADAPTIVE: AtomicBool = AtomicBool::new(true);
+/// Budget the decoders actually evict against; between the minimum and [`MAX_CACHE_SIZE`].
+static EFFECTIVE_CACHE_SIZE: AtomicUsize = AtomicUsize::new(usize::MAX);
+static AVAILABLE_MEMORY: AtomicUsize = AtomicUsize::new(usize::MAX);
+/// Wakes the decoders' GC loops when the budget shrinks below what is cached.
+pub(super) static PRESSURE: Notify = Notify::const_new();
+static MONITOR: Once = Once::new();
+
+/// Memory the system can still hand out, and the total it has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MemoryStatus {
+    available: usize,
+    total: usize,
+}
+
+fn read_meminfo() -> Option<MemoryStatus> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| {
+                value
+                    .trim()
+                    .trim_end_matches("kB")
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+            })
+            .map(|kib| kib * 1024)
+    };
+    Some(MemoryStatus {
+        available: field("MemAvailable")?,
+        total: field("MemTotal")?,
+    })
+}
+
+fn read_bytes(path: &str) -> Option<usize> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Limit and usage of the cgroup the process runs in (v2, then v1), if it has a limit.
+fn read_cgroup() -> Option<MemoryStatus> {
+    let (limit, usage) = match read_bytes("/sys/fs/cgroup/memory.max") {
+        Some(limit) => (limit, read_bytes("/sys/fs/cgroup/memory.current")?),
+        None => (
+            read_bytes("/sys/fs/cgroup/memory/memory.limit_in_bytes")?,
+            read_bytes("/sys/fs/cgroup/memory/memory.usage_in_bytes")?,
+        ),
+    };
+    // v1 reports "no limit" as a huge page-aligned number.
+    if limit >= usize::MAX / 2 {
+        return None;
+    }
+    Some(MemoryStatus {
+        available: limit.saturating_sub(usage),
+        total: limit,
+    })
+}
+
+/// The tighter of the machine and the cgroup. `None` where neither can be read.
+fn memory_status() -> Option<MemoryStatus> {
+    match (read_meminfo(), read_cgroup()) {
+        (Some(system), Some(cgroup)) => Some(MemoryStatus {
+            available: system.available.min(cgroup.available),
+            total: system.total.min(cgroup.total),
+        }),
+        (system, cgroup) => system.or(cgroup),
+    }
+}
+
+/// Budget that keeps the reserve free: what is cached now plus what the system can spare.
+fn adapted_budget(status: MemoryStatus, used: usize, min: usize, max: usize) -> usize {
+    let reserve = MIN_RESERVE.max((status.total as f64 * RESERVE_FRACTION) as usize);
+    let target = used.saturating_add(status.available.saturating_sub(reserve));
+    target.min(max).max(min.min(max))
+}
+
+fn update() {
+    let max = MAX_CACHE_SIZE.load(Ordering::Relaxed);
+    let status = memory_status();
+    AVAILABLE_MEMORY.store(
+        status.map_or(usize::MAX, |status| status.available),
+        Ordering::Relaxed,
+    );
+
+    let effective = match status {
+        Some(status) if ADAPTIVE.load(Ordering::Relaxed) => adapted_budget(
+            status,
+            ENTIRE_CACHE_SIZE.load(Ordering::Relaxed),
+            MIN_CACHE_SIZE.load(Ordering::Relaxed),
+            max,
+        ),
+        _ => max,
+    };
+    let previous = EFFECTIVE_CACHE_SIZE.swap(effective, Ordering::Relaxed);
+    if effective < previous && ENTIRE_CACHE_SIZE.load(Ordering::Relaxed) >= effective {
+        PRESSURE.notify_waiters();
+    }
+}
+
+/// Start watching system memory. Called when the first decoder is created.
+pub(super) fn ensure_monitor() {
+    MONITOR.call_once(|| {
+        update();
+        tokio::spawn(async {
+            loop {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                update();
+            }
+        });
+    });
+}
+
+/// Budget to evict against right now.
+pub(super) fn effective_cache_size() -> usize {
+    EFFECTIVE_CACHE_SIZE
+        .load(Ordering::Relaxed)
+        .min(MAX_CACHE_SIZE.load(Ordering::Relaxed))
+}
+
+/// Lower bound the adaptive budget never shrinks below.
+pub fn set_min_cache_size(bytes: usize) {
+    MIN_CACHE_SIZE.store(bytes.max(MIB), Ordering::Relaxed);
+    update();
+}
+
+/// Turn following system memory on or off; off pins the budget to the maximum.
+pub fn set_adaptive(adaptive: bool) {
+    ADAPTIVE.store(adaptive, Ordering::Relaxed);
+    update();
+}
+
+/// Re-evaluate the budget after the maximum changed.
+pub(super) fn refresh() {
+    update();
+}
+
+/// Bounds and current state of the cache budget, as reported by `GET /cache`.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BudgetStatus {
+    pub adaptive: bool,
+    #[serde(rename = "minBytes")]
+    pub min_bytes: usize,
+    #[serde(rename = "maxBytes")]
+    pub max_bytes: usize,
+    #[serde(rename = "effectiveBytes")]
+    pub effective_bytes: usize,
+    /// Memory available to the process; `None` where it cannot be read.
+    #[serde(rename = "availableBytes")]
+    pub available_bytes: Option<usize>,
+}
+
+pub fn budget_status() -> BudgetStatus {
+    let available = AVAILABLE_MEMORY.load(Ordering::Relaxed);
+    BudgetStatus {
+        adaptive: ADAPTIVE.load(Ordering::Relaxed),
+        min_bytes: MIN_CACHE_SIZE.load(Ordering::Relaxed),
+        max_bytes: MAX_CACHE_SIZE.load(Ordering::Relaxed),
+        effective_bytes: effective_cache_size(),
+        available_bytes: (available != usize::MAX).then_some(available),
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    ops::Range,
+    sync::{Arc, atomic::Ordering},
+};
+
+use tracing::warn;
+
+use crate::{
+    decoder::{
+        CachedDecoder, FrameStream, Inner, StreamError, cached_fps, deinterlace::FieldRate, memory,
+        source_is_animated, store_decoded_frame,
+    },
+    ffmpeg::probe_keyframes,
+};
+
+/// Render plans spanning fewer source frames are left to the main stream.
+const MIN_PARALLEL_SPAN: u32 = 1800;
+const MIN_CHUNK_FRAMES: u32 = 48;
+const MAX_CHUNK_FRAMES: u32 = 600;
+/// Chunks decoded at once, next to the main stream.
+const PARALLEL_CHUNKS: usize = 3;
+/// How far past a chunk boundary to look for a keyframe.
+const KEYFRAME_SEARCH_SECS: f64 = 20.0;
+
+fn eligible(inner: &Inner) -> bool {
+    inner.sequence.is_none() && !inner.preview && inner.deinterlace.rate != Some(FieldRate::Field)
+}
+
+/// Frames per chunk, so that the chunks in flight fit in half the cache budget.
+fn chunk_frames(inner: &Inner) -> u32 {
+    let frame_size = inner.format.frame_size(inner.width, inner.height).max(1);
+    let frames = memory::effective_cache_size() / 2 / PARALLEL_CHUNKS / frame_size;
+    (frames.min(MAX_CHUNK_FRAMES as usize) as u32).max(MIN_CHUNK_FRAMES)
+}
+
+/// Hand the render plan past the read-ahead window to chunk decoders, each starting at a
+/// keyframe. The main stream skips the frames they claim.
+pub(super) fn dispatch(inner: &Arc<Inner>, frame_index: u32) {
+    if !eligible(inner) || inner.dispatching.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let inner = inner.clone();
+    tokio::spawn(async move {
+        // Animated images only decode from the start, so they cannot be split into chunks.
+        if !source_is_animated(&inner.path, &inner.stream).await {
+            dispatch_chunks(&inner, frame_index).await;
+        }
+        inner.dispatching.store(false, Ordering::Relaxed);
+    });
+}
+
+async fn dispatch_chunks(inner: &Arc<Inner>, frame_index: u32) {
+    let chunk_frames = chunk_frames(inner);
+    // Do not run further ahead of the worker than the chunks in flight could cover.
+    let horizon = frame_index.saturating_add(chunk_frames * (PARALLEL_CHUNKS as u32 + 1));
+
+    loop {
+        if inner.closed.load(Ordering::Relaxed)
+            || inner.over_budget()
+            || inner.chunks.lock().unwrap().len() >= PARALLEL_CHUNKS
+        {
+            return;
+        }
+        let (span, after_read_ahead) = match inner.render_plan.lock().unwrap().as_ref() {
+            Some(plan) => (plan.span(), plan.after_read_ahead()),
+            None => return,
+        };
+        let Some(after_read_ahead) = after_read_ahead else {
+            return;
+        };
+        if span.end - span.start < MIN_PARALLEL_SPAN {
+            return;
+        }
+
+        let cursor = *inner.chunk_cursor.lock().unwrap();
+        let start = match cursor {
+            Some(cursor) if cursor >= after_read_ahead => cursor,
+            // First chunk, or the worker overtook the chunks.
+            _ => keyframe_at_or_after(inner, after_read_ahead)
+                .await
+                .unwrap_or(after_read_ahead),
+        };
+        if start >= span.end || start >= horizon {
+            return;
+        }
+        let end = keyframe_at_or_after(inner, start + chunk_frames)
+            .await
+            .filter(|&keyframe| keyframe <= start + chunk_frames * 2)
+            .unwrap_or(start + chunk_frames)
+            .min(span.end);
+        let range = start..end;
+        *inner.chunk_cursor.lock().unwrap() = Some(end);
+
+        let frames = match inner.render_plan.lock().unwrap().as_ref() {
+            Some(plan) => plan.frames_in(&range),
+            None => return,
+        };
+        if frames.is_empty() {
+            continue;
+        }
+        inner.chunks.lock().unwrap().push(range.clone());
+        inner
+            .pending_frames
+            .lock()
+            .unwrap()
+            .retain(|frame| !range.contains(frame));
+        inner.running_decode_tasks.fetch_add(1, Ordering::Relaxed);
+        tokio::spawn(decode_chunk(inner.clone(), range, frames));
+    }
+}
+
+async fn keyframe_at_or_after(inner: &Inner, frame_index: u32) -> Option<u32> {
+    let (path, stream) = (inner.path.clone(), inner.stream.clone());
+    let (fps, keyframes) = tokio::task::spawn_blocking(move || {
+        let fps = cached_fps(&path, &stream);
+        let from = frame_index as f64 / fps;
+        let keyframes = probe_keyframes(&path, &stream, from, KEYFRAME_SEARCH_SECS);
+        (fps, keyframes)
+    })
+    .await
+    .ok()?;
+    keyframes
+        .ok()?
+        .into_iter()
+        .map(|time| (time * fps).round().max(0.0) as u32)
+        .find(|&keyframe| keyframe >= frame_index)
+}
+
+/// Decode the planned `frames` of `range` with a process of its own, then hand whatever it
+/// did not get to back to the main stream.
+async fn decode_chunk(inner: Arc<Inner>, range: Range<u32>, frames: Vec<u32>) {
+    if let Err(error) = read_chunk(&inner, &range, &frames).await {
+        warn!(
+            "chunk {}..{} of {} failed: {error}",
+            range.start, range.end, inner.path
+        );
+    }
+    inner.chunks.lock().unwrap().retain(|chunk| *chunk != range);
+
+    let leftover = {
+        let cached = inner.frames.read().unwrap();
+        frames
+            .into_iter()
+            .filter(|frame_index| {
+                cached
+                    .get(frame_index)
+                    .is_some_and(|future| !future.is_completed())
+            })
+            .collect::<Vec<_>>()
+    };
+    if !leftover.is_empty() && !inner.closed.load(Ordering::Relaxed) {
+        inner.pending_frames.lock().unwrap().extend(leftover);
+        CachedDecoder {
+            inner: inner.clone(),
+        }
+        .ensure_stream_task();
+        inner.stream_notify.notify_one();
+    }
+    inner.running_decode_tasks.fetch_sub(1, Ordering::Relaxed);
+}
+
+async fn read_chunk(inner: &Arc<Inner>, range: &Range<u32>, frames: &[u32]) -> Result<(), String> {
+    let mut stream = match FrameStream::spawn(inner, range.start, true, 1).await {
+        Ok(stream) => stream,
+        Err(_) => FrameStream::spawn(inner, range.start, false, 1).await?,
+    };
+
+    let mut wanted = frames.iter().copied().peekable();
+    let result = loop {
+        if wanted.peek().is_none() {
+            break Ok(());
+        }
+        if inner.closed.load(Ordering::Relaxed) || stream.slot.should_yield() {
+            break Ok(());
+        }
+        let (frame_index, frame) = match stream.read_next().await {
+            Ok(decoded) => decoded,
+            Err(StreamError::End) => break Ok(()),
+            Err(StreamError::Failed(error)) => {
+                stream.note_failure(inner, &error);
+                break Err(error);
+            }
+        };
+        while wanted.next_if(|&wanted| wanted < frame_index).is_some() {}
+        if wanted.next_if_eq(&frame_index).is_some() {
+            store_decoded_frame(inner, frame_index, Arc::new(frame)).await;
+        }
+    };
+    stream.shutdown().await;
+    result
+}
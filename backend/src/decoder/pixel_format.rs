@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ffmpeg::VideoColorInfo;
+
+/// Layout of the frames a decoder produces and sends over `/ws`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PixelFormat {
+    /// Interleaved 8-bit RGBA.
+    #[default]
+    Rgba,
+    /// Planar Y, U, V with 2x2 subsampled chroma.
+    Yuv420p,
+    /// Planar Y followed by interleaved UV with 2x2 subsampled chroma.
+    Nv12,
+    /// Like [`PixelFormat::Yuv420p`] with a full-resolution alpha plane appended.
+    Yuva420p,
+}
+
+/// One plane of a frame, in the order it appears in the buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plane {
+    pub width: u32,
+    pub height: u32,
+    /// Interleaved components per pixel.
+    pub channels: usize,
+}
+
+impl Plane {
+    pub fn byte_len(&self) -> usize {
+        self.width as usize * self.height as usize * self.channels
+    }
+}
+
+impl PixelFormat {
+    pub fn ffmpeg_name(self) -> &'static str {
+        match self {
+            PixelFormat::Rgba => "rgba",
+            PixelFormat::Yuv420p => "yuv420p",
+            PixelFormat::Nv12 => "nv12",
+            PixelFormat::Yuva420p => "yuva420p",
+        }
+    }
+
+    /// Identifier sent in the `/ws` packet header.
+    pub fn code(self) -> u32 {
+        match self {
+            PixelFormat::Rgba => 0,
+            PixelFormat::Yuv420p => 1,
+            PixelFormat::Nv12 => 2,
+            PixelFormat::Yuva420p => 3,
+        }
+    }
+
+    pub fn is_yuv(self) -> bool {
+        self != PixelFormat::Rgba
+    }
+
+    pub fn has_alpha(self) -> bool {
+        matches!(self, PixelFormat::Rgba | PixelFormat::Yuva420p)
+    }
+
+    pub fn planes(self, width: u32, height: u32) -> Vec<Plane> {
+        let luma = Plane {
+            width,
+            height,
+            channels: 1,
+        };
+        let chroma = Plane {
+            width: width.div_ceil(2),
+            height: height.div_ceil(2),
+            channels: 1,
+        };
+
+        match self {
+            PixelFormat::Rgba => vec![Plane {
+                width,
+                height,
+                channels: 4,
+            }],
+            PixelFormat::Yuv420p => vec![luma, chroma, chroma],
+            PixelFormat::Nv12 => vec![
+                luma,
+                Plane {
+                    channels: 2,
+                    ..chroma
+                },
+            ],
+            PixelFormat::Yuva420p => vec![luma, chroma, chroma, luma],
+        }
+    }
+
+    pub fn frame_size(self, width: u32, height: u32) -> usize {
+        self.planes(width, height).iter().map(Plane::byte_len).sum()
+    }
+
+    /// Per-plane value of a black (or, with an alpha plane, transparent) pixel.
+    pub fn fill_values(self, full_range: bool) -> Vec<Vec<u8>> {
+        let black = if full_range { 0 } else { 16 };
+        match self {
+            PixelFormat::Rgba => vec![vec![0, 0, 0, 0]],
+            PixelFormat::Yuv420p => vec![vec![black], vec![128], vec![128]],
+            PixelFormat::Nv12 => vec![vec![black], vec![128, 128]],
+            PixelFormat::Yuva420p => vec![vec![black], vec![128], vec![128], vec![0]],
+        }
+    }
+
+    /// A frame where every plane is filled with [`PixelFormat::fill_values`].
+    pub fn blank_frame(self, width: u32, height: u32, full_range: bool) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(self.frame_size(width, height));
+        for (plane, fill) in self
+            .planes(width, height)
+            .into_iter()
+            .zip(self.fill_values(full_range))
+        {
+            for _ in 0..plane.byte_len() / plane.channels {
+                frame.extend_from_slice(&fill);
+            }
+        }
+        frame
+    }
+}
+
+/// YUV to RGB matrix the client shader has to apply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMatrix {
+    Bt601,
+    Bt709,
+    Bt2020,
+}
+
+impl ColorMatrix {
+    pub fn code(self) -> u32 {
+        match self {
+            ColorMatrix::Bt601 => 0,
+            ColorMatrix::Bt709 => 1,
+            ColorMatrix::Bt2020 => 2,
+        }
+    }
+
+    /// Matrix of a source, guessing from its height when the file does not say.
+    pub fn from_color_info(info: &VideoColorInfo, height: u32) -> Self {
+        match info.color_space.as_deref() {
+            Some("bt709") => ColorMatrix::Bt709,
+            Some("smpte170m" | "bt470bg" | "fcc") => ColorMatrix::Bt601,
+            Some("bt2020nc" | "bt2020c") => ColorMatrix::Bt2020,
+            _ if height <= 576 => ColorMatrix::Bt601,
+            _ => ColorMatrix::Bt709,
+        }
+    }
+}
+
+/// Whether a source uses full-range ("pc"/"jpeg") YUV.
+pub fn is_full_range(info: &VideoColorInfo) -> bool {
+    match info.color_range.as_deref() {
+        Some("pc" | "jpeg") => true,
+        Some("tv" | "mpeg") => false,
+        _ => info
+            .pix_fmt
+            .as_deref()
+            .is_some_and(|pix_fmt| pix_fmt.starts_with("yuvj")),
+    }
+}
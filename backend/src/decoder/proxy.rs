@@ -0,0 +1,438 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{LazyLock, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    sync::Semaphore,
+    task::AbortHandle,
+};
+use tracing::{info, warn};
+
+use crate::{
+    decoder::{
+        DEFAULT_VIDEO_STREAM,
+        color::{ColorOptions, scale_filter, zscale_available},
+        deinterlace::DeinterlaceOptions,
+        pixel_format::PixelFormat,
+        scheduler::{self, Priority},
+        source::is_sequence_pattern,
+        source_codec, source_color, source_field_order, source_geometry, source_is_animated,
+        watch::file_identity,
+    },
+    ffmpeg::{bin::ffmpeg_path, probe_video_duration_ms},
+};
+
+/// Proxy height used when a request does not name one.
+pub const DEFAULT_PROXY_HEIGHT: u32 = 540;
+
+/// Codec of a proxy. Both are intra-only, so any frame decodes without its neighbours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyCodec {
+    /// All-intra H.264. Smaller files; needs an ffmpeg built with libx264.
+    #[default]
+    H264,
+    Mjpeg,
+}
+
+impl ProxyCodec {
+    fn encoder_args(self) -> &'static [&'static str] {
+        match self {
+            Self::H264 => &[
+                "-c:v",
+                "libx264",
+                "-preset",
+                "veryfast",
+                "-tune",
+                "fastdecode",
+                "-x264-params",
+                "keyint=1",
+                "-crf",
+                "20",
+                "-pix_fmt",
+                "yuv420p",
+                "-color_range",
+                "tv",
+            ],
+            Self::Mjpeg => &[
+                "-c:v",
+                "mjpeg",
+                "-q:v",
+                "3",
+                "-pix_fmt",
+                "yuvj420p",
+                "-color_range",
+                "pc",
+            ],
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::H264 => "h264",
+            Self::Mjpeg => "mjpeg",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyState {
+    Queued,
+    Running,
+    Ready,
+    Failed,
+    /// The source is already small enough, or cannot be proxied (alpha, sequences).
+    Unnecessary,
+}
+
+/// Proxy of one source, as reported by `/proxy`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyStatus {
+    pub path: String,
+    #[serde(rename = "proxyPath")]
+    pub proxy_path: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub codec: ProxyCodec,
+    pub state: ProxyState,
+    /// Fraction of the source encoded so far, 0 to 1.
+    pub progress: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A finished proxy the decoder may read instead of its source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct ReadyProxy {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+static PROXIES: LazyLock<Mutex<HashMap<String, ProxyStatus>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+/// Proxies are encoded one at a time; each encode already uses every core.
+static ENCODER: Semaphore = Semaphore::const_new(1);
+/// Queued and running encodes, so that a source that changes can stop its encode.
+static JOBS: LazyLock<Mutex<HashMap<String, AbortHandle>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn proxy_dir() -> PathBuf {
+    std::env::var("FRAMESCRIPT_PROXY_DIR")
+        .ok()
+        .filter(|dir| !dir.trim().is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            dirs::cache_dir()
+                .unwrap_or_else(std::env::temp_dir)
+                .join("framescript")
+                .join("proxies")
+        })
+}
+
+/// File name derived from the source's identity, so an edited source gets a new proxy.
+fn proxy_file(path: &str, height: u32, codec: ProxyCodec) -> Option<PathBuf> {
+    let identity = file_identity(path)?;
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    identity.hash(&mut hasher);
+    let stem = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Some(proxy_dir().join(format!(
+        "{stem}-{:016x}-{height}p-{}.mov",
+        hasher.finish(),
+        codec.name()
+    )))
+}
+
+pub fn status(path: &str) -> Option<ProxyStatus> {
+    PROXIES.lock().unwrap().get(path).cloned()
+}
+
+pub fn list() -> Vec<ProxyStatus> {
+    let mut proxies = PROXIES
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    proxies.sort_by(|a, b| a.path.cmp(&b.path));
+    proxies
+}
+
+/// Proxy of `path` that can stand in for decoding at `width`x`height`.
+pub(super) fn ready_proxy(path: &str, width: u32, height: u32) -> Option<ReadyProxy> {
+    let proxies = PROXIES.lock().unwrap();
+    let status = proxies.get(path)?;
+    if status.state != ProxyState::Ready || width > status.width || height > status.height {
+        return None;
+    }
+    let proxy_path = status.proxy_path.clone()?;
+    // The proxy may have been deleted behind our back.
+    if !Path::new(&proxy_path).is_file() {
+        return None;
+    }
+    Some(ReadyProxy {
+        path: proxy_path,
+        width: status.width,
+        height: status.height,
+    })
+}
+
+/// Where an encode writes before moving the proxy into place.
+fn partial_file(proxy_path: &str) -> String {
+    format!("{proxy_path}.part.mov")
+}
+
+/// Forget the proxy of a source that changed. A finished proxy is deleted; an encode still
+/// underway is stopped and its partial output deleted.
+pub(super) fn forget(path: &str) {
+    let Some(status) = PROXIES.lock().unwrap().remove(path) else {
+        return;
+    };
+    if let Some(job) = JOBS.lock().unwrap().remove(path) {
+        // ffmpeg is killed when the aborted job drops it.
+        job.abort();
+    }
+    let Some(proxy_path) = status.proxy_path else {
+        return;
+    };
+    match status.state {
+        ProxyState::Ready => {
+            let _ = std::fs::remove_file(proxy_path);
+        }
+        ProxyState::Queued | ProxyState::Running => {
+            let _ = std::fs::remove_file(partial_file(&proxy_path));
+        }
+        ProxyState::Failed | ProxyState::Unnecessary => {}
+    }
+}
+
+fn update(path: &str, change: impl FnOnce(&mut ProxyStatus)) {
+    if let Some(status) = PROXIES.lock().unwrap().get_mut(path) {
+        change(status);
+    }
+}
+
+/// Start generating a proxy of `path` in the background, unless one exists or is underway.
+pub async fn request(path: &str, height: Option<u32>, codec: Option<ProxyCodec>) -> ProxyStatus {
+    let height = height.unwrap_or(DEFAULT_PROXY_HEIGHT).clamp(144, 2160) & !1;
+    let codec = codec.unwrap_or_default();
+    if let Some(status) = status(path) {
+        let same = status.height == height && status.codec == codec;
+        if same && status.state != ProxyState::Failed {
+            return status;
+        }
+        if matches!(status.state, ProxyState::Queued | ProxyState::Running) {
+            return status;
+        }
+    }
+
+    let mut status = ProxyStatus {
+        path: path.to_string(),
+        proxy_path: None,
+        width: 0,
+        height,
+        codec,
+        state: ProxyState::Queued,
+        progress: 0.0,
+        error: None,
+    };
+
+    let stream = DEFAULT_VIDEO_STREAM;
+    let geometry = source_geometry(path, stream).await;
+    let has_alpha = source_codec(path, stream)
+        .await
+        .is_some_and(|codec| codec.has_alpha);
+    let animated = source_is_animated(path, stream).await;
+    let display = geometry.map(|geometry| geometry.display_dimensions());
+    match display {
+        _ if is_sequence_pattern(path) || animated || has_alpha => {
+            status.state = ProxyState::Unnecessary;
+        }
+        None => {
+            status.state = ProxyState::Failed;
+            status.error = Some("failed to probe source".to_string());
+        }
+        Some((_, source_height)) if source_height <= height => {
+            status.state = ProxyState::Unnecessary;
+        }
+        Some((source_width, source_height)) => {
+            let width =
+                ((source_width as u64 * height as u64 / source_height as u64) as u32 + 1) & !1;
+            status.width = width.max(2);
+            status.proxy_path =
+                proxy_file(path, height, codec).map(|file| file.to_string_lossy().into_owned());
+            if status.proxy_path.is_none() {
+                status.state = ProxyState::Failed;
+                status.error = Some("source not found".to_string());
+            }
+        }
+    }
+
+    if let Some(proxy_path) = &status.proxy_path
+        && Path::new(proxy_path).is_file()
+    {
+        status.state = ProxyState::Ready;
+        status.progress = 1.0;
+    }
+
+    PROXIES
+        .lock()
+        .unwrap()
+        .insert(path.to_string(), status.clone());
+
+    if status.state == ProxyState::Queued {
+        let job = status.clone();
+        let handle = tokio::spawn(async move {
+            let path = job.path.clone();
+            let result = generate(job).await;
+            JOBS.lock().unwrap().remove(&path);
+            update(&path, |status| match result {
+                Ok(()) => {
+                    info!("proxy ready for {path}");
+                    status.state = ProxyState::Ready;
+                    status.progress = 1.0;
+                }
+                Err(error) => {
+                    warn!("proxy generation failed for {path}: {error}");
+                    status.state = ProxyState::Failed;
+                    status.error = Some(error);
+                }
+            });
+        });
+        JOBS.lock()
+            .unwrap()
+            .insert(path.to_string(), handle.abort_handle());
+    }
+    status
+}
+
+async fn generate(job: ProxyStatus) -> Result<(), String> {
+    let _permit = ENCODER
+        .acquire()
+        .await
+        .map_err(|error| format!("encoder unavailable: {error}"))?;
+    let _slot = scheduler::acquire(Priority::Background, 0).await;
+    update(&job.path, |status| status.state = ProxyState::Running);
+
+    let path = job.path.as_str();
+    let output = job
+        .proxy_path
+        .clone()
+        .ok_or_else(|| "missing proxy path".to_string())?;
+    let stream = DEFAULT_VIDEO_STREAM;
+
+    // Bake deinterlacing, rotation, square pixels and SDR BT.709 into the proxy so that the
+    // decoder can treat it as a plain progressive source.
+    let field_order = source_field_order(path, stream).await.unwrap_or_default();
+    let color = source_color(path, stream).await.unwrap_or_default();
+    let coded_height = source_geometry(path, stream)
+        .await
+        .map(|geometry| geometry.coded_height)
+        .unwrap_or(job.height);
+    let mut filter = String::new();
+    if let Some(deinterlace) = DeinterlaceOptions::default().filter(field_order) {
+        filter.push_str(&deinterlace);
+        filter.push(',');
+    }
+    filter.push_str(&scale_filter(
+        &color,
+        &ColorOptions::default(),
+        job.width,
+        job.height,
+        coded_height,
+        PixelFormat::Yuv420p,
+        zscale_available().await,
+    ));
+    filter.push_str(",setsar=1,format=yuv420p");
+
+    let duration_us = {
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || probe_video_duration_ms(&path, stream))
+            .await
+            .ok()
+            .and_then(|result| result.ok())
+            .unwrap_or(0)
+            .saturating_mul(1000)
+    };
+
+    let dir = proxy_dir();
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|error| format!("failed to create {}: {error}", dir.display()))?;
+    let temp = partial_file(&output);
+
+    let mut cmd = Command::new(ffmpeg_path()?);
+    cmd.arg("-hide_banner")
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-nostdin")
+        .arg("-y")
+        .arg("-i")
+        .arg(path)
+        .arg("-map")
+        .arg(format!("0:{stream}"))
+        .arg("-an")
+        .arg("-sn")
+        .arg("-dn")
+        .arg("-vf")
+        .arg(&filter)
+        .arg("-fps_mode")
+        .arg("passthrough")
+        .args(job.codec.encoder_args())
+        .arg("-colorspace")
+        .arg("bt709")
+        .arg("-color_primaries")
+        .arg("bt709")
+        .arg("-color_trc")
+        .arg("bt709")
+        .arg("-progress")
+        .arg("pipe:1")
+        .arg("-nostats")
+        .arg(&temp)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true);
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|error| format!("failed to run ffmpeg: {error}"))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| "failed to open ffmpeg stdout".to_string())?;
+
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Some(value) = line.strip_prefix("out_time_us=") else {
+            continue;
+        };
+        if let (Ok(done), true) = (value.trim().parse::<u64>(), duration_us > 0) {
+            let progress = (done as f64 / duration_us as f64).clamp(0.0, 0.99);
+            update(path, |status| status.progress = progress);
+        }
+    }
+
+    let status = child
+        .wait()
+        .await
+        .map_err(|error| format!("failed to wait on ffmpeg: {error}"))?;
+    if !status.success() {
+        let _ = tokio::fs::remove_file(&temp).await;
+        return Err(format!("ffmpeg failed with status: {status}"));
+    }
+    tokio::fs::rename(&temp, &output)
+        .await
+        .map_err(|error| format!("failed to move proxy into place: {error}"))
+}
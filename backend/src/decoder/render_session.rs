@@ -0,0 +1,221 @@
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    sync::{LazyLock, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Source frames queued ahead of the one a render worker is on.
+const READ_AHEAD: usize = 24;
+
+/// Project frames `start..end` rendered by one worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerRange {
+    pub worker: u32,
+    pub start: u32,
+    pub end: u32,
+}
+
+/// Worker ranges of the running render, registered by the render job.
+static WORKERS: LazyLock<Mutex<BTreeMap<u32, WorkerRange>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+pub fn register(workers: Vec<WorkerRange>) {
+    let mut registered = WORKERS.lock().unwrap();
+    registered.clear();
+    for range in workers {
+        registered.insert(range.worker, range);
+    }
+}
+
+pub fn clear() {
+    WORKERS.lock().unwrap().clear();
+}
+
+pub fn workers() -> Vec<WorkerRange> {
+    WORKERS.lock().unwrap().values().copied().collect()
+}
+
+pub fn worker_range(worker: u32) -> Option<WorkerRange> {
+    WORKERS.lock().unwrap().get(&worker).copied()
+}
+
+/// How a clip maps project frames to frames of its source, mirroring the render page.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ClipMapping {
+    /// Project frame the clip starts at.
+    pub start: u32,
+    /// Length of the clip in project frames.
+    pub frames: u32,
+    #[serde(rename = "trimStart", default)]
+    pub trim_start: u32,
+    #[serde(rename = "projectFps")]
+    pub project_fps: f64,
+    /// Frame rate of the source; 0 when unknown, in which case frames map one to one.
+    #[serde(rename = "sourceFps", default)]
+    pub source_fps: f64,
+    #[serde(rename = "sourceStart", default)]
+    pub source_start: u32,
+    #[serde(rename = "sourceEnd")]
+    pub source_end: u32,
+}
+
+impl ClipMapping {
+    fn source_frame(&self, project_frame: u32) -> u32 {
+        let local = (project_frame - self.start) as u64 + self.trim_start as u64;
+        let frame = if self.source_fps > 0.0 && self.project_fps > 0.0 {
+            (local as f64 * self.source_fps / self.project_fps).floor() as u64
+        } else {
+            local
+        };
+        (frame.min(u32::MAX as u64) as u32)
+            .clamp(self.source_start, self.source_end.max(self.source_start))
+    }
+}
+
+/// Source frames a worker needs from a clip, in the order it needs them.
+pub fn plan(range: WorkerRange, clip: &ClipMapping) -> Vec<u32> {
+    let start = range.start.max(clip.start);
+    let end = range.end.min(clip.start.saturating_add(clip.frames));
+    let mut frames = (start..end)
+        .map(|project_frame| clip.source_frame(project_frame))
+        .collect::<Vec<_>>();
+    frames.dedup();
+    frames
+}
+
+/// A decoder's progress through its plan.
+#[derive(Debug)]
+pub(super) struct RenderPlan {
+    frames: Vec<u32>,
+    cursor: usize,
+}
+
+impl RenderPlan {
+    pub(super) fn new(frames: Vec<u32>) -> Self {
+        Self { frames, cursor: 0 }
+    }
+
+    /// The worker is on `frame_index`. Returns the planned frames it is done with and the
+    /// frames to decode next.
+    pub(super) fn advance(&mut self, frame_index: u32) -> (Vec<u32>, Vec<u32>) {
+        let next = self.frames.partition_point(|&frame| frame < frame_index);
+        let used = self.frames[self.cursor.min(next)..next].to_vec();
+        self.cursor = self.cursor.max(next);
+        let end = (self.cursor + READ_AHEAD).min(self.frames.len());
+        (used, self.frames[self.cursor..end].to_vec())
+    }
+
+    /// Source frames the plan spans, from its first to one past its last.
+    pub(super) fn span(&self) -> Range<u32> {
+        match (self.frames.first(), self.frames.last()) {
+            (Some(&first), Some(&last)) => first..last + 1,
+            _ => 0..0,
+        }
+    }
+
+    /// First planned frame past the read-ahead window; `None` when the window reaches the end.
+    pub(super) fn after_read_ahead(&self) -> Option<u32> {
+        self.frames.get(self.cursor + READ_AHEAD).copied()
+    }
+
+    pub(super) fn frames_in(&self, range: &Range<u32>) -> Vec<u32> {
+        let start = self.frames.partition_point(|&frame| frame < range.start);
+        let end = self.frames.partition_point(|&frame| frame < range.end);
+        self.frames[start..end].to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(project_fps: f64, source_fps: f64) -> ClipMapping {
+        ClipMapping {
+            start: 10,
+            frames: 5,
+            trim_start: 2,
+            project_fps,
+            source_fps,
+            source_start: 0,
+            source_end: 100,
+        }
+    }
+
+    fn worker(start: u32, end: u32) -> WorkerRange {
+        WorkerRange {
+            worker: 0,
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn clip_maps_project_frames_to_source_frames() {
+        let same_rate = clip(30.0, 30.0);
+        assert_eq!(same_rate.source_frame(10), 2);
+        assert_eq!(same_rate.source_frame(14), 6);
+
+        let half_rate = clip(30.0, 15.0);
+        let frames = (10..15)
+            .map(|frame| half_rate.source_frame(frame))
+            .collect::<Vec<_>>();
+        assert_eq!(frames, [1, 1, 2, 2, 3]);
+
+        let unknown_rate = clip(30.0, 0.0);
+        assert_eq!(unknown_rate.source_frame(14), 6);
+    }
+
+    #[test]
+    fn clip_clamps_to_the_source_range() {
+        let clamped = ClipMapping {
+            source_start: 4,
+            source_end: 5,
+            ..clip(30.0, 30.0)
+        };
+        assert_eq!(clamped.source_frame(10), 4);
+        assert_eq!(clamped.source_frame(14), 5);
+    }
+
+    #[test]
+    fn plan_covers_the_worker_range_once() {
+        let half_rate = clip(30.0, 15.0);
+        assert_eq!(plan(worker(0, 100), &half_rate), [1, 2, 3]);
+        assert_eq!(plan(worker(12, 14), &half_rate), [2]);
+        assert!(plan(worker(20, 30), &half_rate).is_empty());
+    }
+
+    #[test]
+    fn render_plan_advances_through_its_frames() {
+        let mut plan = RenderPlan::new((0..40).collect());
+        assert_eq!(plan.span(), 0..40);
+
+        let (used, next) = plan.advance(0);
+        assert!(used.is_empty());
+        assert_eq!(next, (0..24).collect::<Vec<_>>());
+
+        let (used, next) = plan.advance(5);
+        assert_eq!(used, [0, 1, 2, 3, 4]);
+        assert_eq!(next, (5..29).collect::<Vec<_>>());
+        assert_eq!(plan.after_read_ahead(), Some(29));
+
+        // Going back does not release frames again or move the cursor.
+        let (used, next) = plan.advance(3);
+        assert!(used.is_empty());
+        assert_eq!(next, (5..29).collect::<Vec<_>>());
+
+        let (used, next) = plan.advance(39);
+        assert_eq!(used, (5..39).collect::<Vec<_>>());
+        assert_eq!(next, [39]);
+        assert_eq!(plan.after_read_ahead(), None);
+    }
+
+    #[test]
+    fn render_plan_lists_frames_in_a_range() {
+        let plan = RenderPlan::new(vec![1, 3, 5, 7]);
+        assert_eq!(plan.frames_in(&(2..6)), [3, 5]);
+        assert!(plan.frames_in(&(8..10)).is_empty());
+        assert_eq!(RenderPlan::new(Vec::new()).span(), 0..0);
+    }
+}
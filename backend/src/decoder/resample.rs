@@ -0,0 +1,467 @@
+use serde::Deserialize;
+
+use crate::decoder::pixel_format::PixelFormat;
+
+/// Pixel filter used when resizing a cached frame to the requested size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleAlgorithm {
+    Nearest,
+    #[default]
+    Bilinear,
+    /// Box filter. Averages every covered source pixel when shrinking.
+    Area,
+}
+
+/// How the source aspect ratio is mapped onto the requested size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    /// Scale each axis independently to the requested size.
+    #[default]
+    Stretch,
+    /// Scale to fit inside the requested size, padding the rest.
+    Fit,
+    /// Scale to cover the requested size, cropping the overflow.
+    Fill,
+    /// Do not scale. Cut the center of the source (or pad it) to the requested size.
+    Crop,
+}
+
+struct Layout {
+    // Source rectangle (in source pixels) mapped onto the destination rectangle.
+    src_x: f64,
+    src_y: f64,
+    src_w: f64,
+    src_h: f64,
+    dst_x: u32,
+    dst_y: u32,
+    dst_w: u32,
+    dst_h: u32,
+}
+
+fn layout(fit: FitMode, sw: u32, sh: u32, dw: u32, dh: u32, source_scale: f64) -> Layout {
+    let (swf, shf, dwf, dhf) = (sw as f64, sh as f64, dw as f64, dh as f64);
+    match fit {
+        FitMode::Stretch => Layout {
+            src_x: 0.0,
+            src_y: 0.0,
+            src_w: swf,
+            src_h: shf,
+            dst_x: 0,
+            dst_y: 0,
+            dst_w: dw,
+            dst_h: dh,
+        },
+        FitMode::Fit => {
+            let scale = (dwf / swf).min(dhf / shf);
+            let content_w = ((swf * scale).round() as u32).clamp(1, dw);
+            let content_h = ((shf * scale).round() as u32).clamp(1, dh);
+            Layout {
+                src_x: 0.0,
+                src_y: 0.0,
+                src_w: swf,
+                src_h: shf,
+                dst_x: (dw - content_w) / 2,
+                dst_y: (dh - content_h) / 2,
+                dst_w: content_w,
+                dst_h: content_h,
+            }
+        }
+        FitMode::Fill => {
+            let scale = (dwf / swf).max(dhf / shf);
+            let src_w = dwf / scale;
+            let src_h = dhf / scale;
+            Layout {
+                src_x: (swf - src_w) / 2.0,
+                src_y: (shf - src_h) / 2.0,
+                src_w,
+                src_h,
+                dst_x: 0,
+                dst_y: 0,
+                dst_w: dw,
+                dst_h: dh,
+            }
+        }
+        FitMode::Crop => {
+            // Requested pixels are source pixels. `source_scale` converts them into cached
+            // pixels, so a crop from a reduced-resolution cache covers the same area.
+            let content_w = ((swf * source_scale).round() as u32).clamp(1, dw);
+            let content_h = ((shf * source_scale).round() as u32).clamp(1, dh);
+            let src_w = content_w as f64 / source_scale;
+            let src_h = content_h as f64 / source_scale;
+            Layout {
+                src_x: (swf - src_w) / 2.0,
+                src_y: (shf - src_h) / 2.0,
+                src_w,
+                src_h,
+                dst_x: (dw - content_w) / 2,
+                dst_y: (dh - content_h) / 2,
+                dst_w: content_w,
+                dst_h: content_h,
+            }
+        }
+    }
+}
+
+impl Layout {
+    /// The same mapping on a plane subsampled from `sw`x`sh`/`dw`x`dh` to
+    /// `plane_sw`x`plane_sh`/`plane_dw`x`plane_dh`, so chroma lines up with luma. The
+    /// destination rectangle is widened to whole plane pixels.
+    fn subsampled(
+        &self,
+        (sw, sh, dw, dh): (u32, u32, u32, u32),
+        (plane_sw, plane_sh, plane_dw, plane_dh): (u32, u32, u32, u32),
+    ) -> Layout {
+        let ratio = |plane: u32, full: u32| plane as f64 / full.max(1) as f64;
+        let (src_x, src_y) = (ratio(plane_sw, sw), ratio(plane_sh, sh));
+        let (dst_x, dst_y) = (ratio(plane_dw, dw), ratio(plane_dh, dh));
+        let start = |value: u32, scale: f64| (value as f64 * scale).floor() as u32;
+        let end =
+            |value: u32, scale: f64, limit: u32| ((value as f64 * scale).ceil() as u32).min(limit);
+        let left = start(self.dst_x, dst_x).min(plane_dw.saturating_sub(1));
+        let top = start(self.dst_y, dst_y).min(plane_dh.saturating_sub(1));
+        let right = end(self.dst_x + self.dst_w, dst_x, plane_dw).max(left + 1);
+        let bottom = end(self.dst_y + self.dst_h, dst_y, plane_dh).max(top + 1);
+        Layout {
+            src_x: self.src_x * src_x,
+            src_y: self.src_y * src_y,
+            src_w: self.src_w * src_x,
+            src_h: self.src_h * src_y,
+            dst_x: left,
+            dst_y: top,
+            dst_w: right - left,
+            dst_h: bottom - top,
+        }
+    }
+}
+
+/// Resize an interleaved 8-bit image with `channels` components per pixel.
+///
+/// Pixels of the destination not covered by the source (letterboxing in [`FitMode::Fit`]
+/// and [`FitMode::Crop`]) are set to `fill`, which must have `channels` entries.
+/// `source_scale` is the ratio between the original source size and `sw`/`sh`; it only
+/// matters for [`FitMode::Crop`].
+#[allow(clippy::too_many_arguments)]
+pub fn resample(
+    src: &[u8],
+    sw: u32,
+    sh: u32,
+    channels: usize,
+    dw: u32,
+    dh: u32,
+    algorithm: ScaleAlgorithm,
+    fit: FitMode,
+    source_scale: f64,
+    fill: &[u8],
+) -> Vec<u8> {
+    if sw == 0 || sh == 0 || dw == 0 || dh == 0 {
+        return vec![0u8; dw as usize * dh as usize * channels];
+    }
+    let layout = layout(fit, sw, sh, dw, dh, source_scale.max(f64::EPSILON));
+    resample_layout(src, (sw, sh), channels, (dw, dh), algorithm, &layout, fill)
+}
+
+/// [`resample`] onto an already computed [`Layout`].
+fn resample_layout(
+    src: &[u8],
+    (sw, sh): (u32, u32),
+    channels: usize,
+    (dw, dh): (u32, u32),
+    algorithm: ScaleAlgorithm,
+    layout: &Layout,
+    fill: &[u8],
+) -> Vec<u8> {
+    let mut dst = vec![0u8; dw as usize * dh as usize * channels];
+    if sw == 0 || sh == 0 || dw == 0 || dh == 0 || src.len() < sw as usize * sh as usize * channels
+    {
+        return dst;
+    }
+
+    for pixel in dst.chunks_exact_mut(channels) {
+        pixel.copy_from_slice(&fill[..channels]);
+    }
+
+    let axis_x = Axis::new(layout.src_x, layout.src_w, layout.dst_w, sw, algorithm);
+    let axis_y = Axis::new(layout.src_y, layout.src_h, layout.dst_h, sh, algorithm);
+    let src_stride = sw as usize * channels;
+    let dst_stride = dw as usize * channels;
+
+    let mut acc = vec![0f32; channels];
+    for (oy, taps_y) in axis_y.pixels().enumerate() {
+        let row = (layout.dst_y as usize + oy) * dst_stride;
+        for (ox, taps_x) in axis_x.pixels().enumerate() {
+            acc.iter_mut().for_each(|value| *value = 0.0);
+            for &(sy, wy) in taps_y {
+                let src_row = sy as usize * src_stride;
+                for &(sx, wx) in taps_x {
+                    let weight = wx * wy;
+                    let offset = src_row + sx as usize * channels;
+                    for (c, value) in acc.iter_mut().enumerate() {
+                        *value += src[offset + c] as f32 * weight;
+                    }
+                }
+            }
+
+            let offset = row + (layout.dst_x as usize + ox) * channels;
+            for (c, value) in acc.iter().enumerate() {
+                dst[offset + c] = value.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    dst
+}
+
+/// Precomputed source taps (index, weight) for every destination pixel along one axis,
+/// stored back to back.
+struct Axis {
+    taps: Vec<(u32, f32)>,
+    /// End of each destination pixel's taps in `taps`.
+    ends: Vec<usize>,
+}
+
+impl Axis {
+    fn new(
+        src_start: f64,
+        src_len: f64,
+        dst_len: u32,
+        src_size: u32,
+        algorithm: ScaleAlgorithm,
+    ) -> Self {
+        let step = src_len / dst_len.max(1) as f64;
+        let last_index = src_size.saturating_sub(1);
+        let last = last_index as f64;
+        let clamp = |value: f64| value.clamp(0.0, last) as u32;
+
+        let mut taps = Vec::with_capacity(dst_len as usize * 2);
+        let mut ends = Vec::with_capacity(dst_len as usize);
+        for i in 0..dst_len {
+            let center = src_start + (i as f64 + 0.5) * step;
+            match algorithm {
+                ScaleAlgorithm::Nearest => taps.push((clamp(center.floor()), 1.0)),
+                ScaleAlgorithm::Area if step > 1.0 => {
+                    // A window starting past the edge still covers the last pixel.
+                    let from = ((center - step / 2.0).floor().max(0.0) as u32).min(last_index);
+                    let to = ((center + step / 2.0).ceil() as u32).clamp(from + 1, src_size);
+                    let weight = 1.0 / (to - from) as f32;
+                    taps.extend((from..to).map(|index| (index, weight)));
+                }
+                ScaleAlgorithm::Bilinear | ScaleAlgorithm::Area => {
+                    let position = center - 0.5;
+                    let base = position.floor();
+                    let frac = (position - base) as f32;
+                    let a = clamp(base);
+                    let b = clamp(base + 1.0);
+                    if a == b || frac == 0.0 {
+                        taps.push((a, 1.0));
+                    } else {
+                        taps.extend([(a, 1.0 - frac), (b, frac)]);
+                    }
+                }
+            }
+            ends.push(taps.len());
+        }
+
+        Self { taps, ends }
+    }
+
+    /// Taps of each destination pixel in order.
+    fn pixels(&self) -> impl Iterator<Item = &[(u32, f32)]> {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        starts
+            .zip(&self.ends)
+            .map(|(start, &end)| &self.taps[start..end])
+    }
+}
+
+/// Resize every plane of a frame in `format`. Chroma planes use the luma plane's layout,
+/// scaled by their subsampling.
+#[allow(clippy::too_many_arguments)]
+pub fn resample_frame(
+    format: PixelFormat,
+    src: &[u8],
+    sw: u32,
+    sh: u32,
+    dw: u32,
+    dh: u32,
+    algorithm: ScaleAlgorithm,
+    fit: FitMode,
+    source_scale: f64,
+    full_range: bool,
+) -> Vec<u8> {
+    let mut dst = Vec::with_capacity(format.frame_size(dw, dh));
+    if sw == 0 || sh == 0 || dw == 0 || dh == 0 {
+        dst.resize(format.frame_size(dw, dh), 0);
+        return dst;
+    }
+    let luma = layout(fit, sw, sh, dw, dh, source_scale.max(f64::EPSILON));
+    let mut offset = 0;
+    for ((src_plane, dst_plane), fill) in format
+        .planes(sw, sh)
+        .into_iter()
+        .zip(format.planes(dw, dh))
+        .zip(format.fill_values(full_range))
+    {
+        let end = (offset + src_plane.byte_len()).min(src.len());
+        let layout = luma.subsampled(
+            (sw, sh, dw, dh),
+            (
+                src_plane.width,
+                src_plane.height,
+                dst_plane.width,
+                dst_plane.height,
+            ),
+        );
+        dst.extend(resample_layout(
+            &src[offset.min(end)..end],
+            (src_plane.width, src_plane.height),
+            src_plane.channels,
+            (dst_plane.width, dst_plane.height),
+            algorithm,
+            &layout,
+            &fill,
+        ));
+        offset = end;
+    }
+    dst
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taps(axis: &Axis) -> Vec<Vec<(u32, f32)>> {
+        axis.pixels().map(<[_]>::to_vec).collect()
+    }
+
+    #[test]
+    fn axis_picks_nearest_source_pixels() {
+        let axis = Axis::new(0.0, 4.0, 2, 4, ScaleAlgorithm::Nearest);
+        assert_eq!(taps(&axis), [vec![(1, 1.0)], vec![(3, 1.0)]]);
+    }
+
+    #[test]
+    fn axis_averages_covered_pixels_when_shrinking() {
+        let axis = Axis::new(0.0, 4.0, 2, 4, ScaleAlgorithm::Area);
+        assert_eq!(
+            taps(&axis),
+            [vec![(0, 0.5), (1, 0.5)], vec![(2, 0.5), (3, 0.5)]]
+        );
+    }
+
+    #[test]
+    fn axis_keeps_area_windows_inside_the_source() {
+        let axis = Axis::new(3.5, 4.0, 1, 4, ScaleAlgorithm::Area);
+        assert_eq!(taps(&axis), [vec![(3, 1.0)]]);
+    }
+
+    #[test]
+    fn axis_interpolates_between_neighbours() {
+        let same_size = Axis::new(0.0, 4.0, 4, 4, ScaleAlgorithm::Bilinear);
+        assert_eq!(
+            taps(&same_size),
+            [
+                vec![(0, 1.0)],
+                vec![(1, 1.0)],
+                vec![(2, 1.0)],
+                vec![(3, 1.0)]
+            ]
+        );
+
+        let doubled = Axis::new(0.0, 2.0, 4, 2, ScaleAlgorithm::Bilinear);
+        assert_eq!(
+            taps(&doubled),
+            [
+                vec![(0, 1.0)],
+                vec![(0, 0.75), (1, 0.25)],
+                vec![(0, 0.25), (1, 0.75)],
+                vec![(1, 1.0)]
+            ]
+        );
+    }
+
+    #[test]
+    fn stretch_to_the_same_size_copies_the_source() {
+        let src = (0..16).collect::<Vec<u8>>();
+        let dst = resample(
+            &src,
+            4,
+            4,
+            1,
+            4,
+            4,
+            ScaleAlgorithm::Bilinear,
+            FitMode::Stretch,
+            1.0,
+            &[0],
+        );
+        assert_eq!(dst, src);
+    }
+
+    #[test]
+    fn fit_pads_the_uncovered_area() {
+        let dst = resample(
+            &[10, 20, 30, 40],
+            2,
+            2,
+            1,
+            4,
+            2,
+            ScaleAlgorithm::Nearest,
+            FitMode::Fit,
+            1.0,
+            &[99],
+        );
+        assert_eq!(dst, [99, 10, 20, 99, 99, 30, 40, 99]);
+    }
+
+    #[test]
+    fn crop_maps_reduced_caches_onto_source_pixels() {
+        // A half-resolution cache of a 4x4 source, cropped to 2x2 source pixels.
+        let dst = resample(
+            &[10, 20, 30, 40],
+            2,
+            2,
+            1,
+            2,
+            2,
+            ScaleAlgorithm::Nearest,
+            FitMode::Crop,
+            2.0,
+            &[99],
+        );
+        assert_eq!(dst, [10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn chroma_follows_the_luma_layout() {
+        // 4x2 YUV 4:2:0 fitted into 8x7: luma content covers rows 1..5, so chroma needs 0..3.
+        let mut src = vec![200; 8];
+        src.extend([50; 4]);
+        let dst = resample_frame(
+            PixelFormat::Yuv420p,
+            &src,
+            4,
+            2,
+            8,
+            7,
+            ScaleAlgorithm::Nearest,
+            FitMode::Fit,
+            1.0,
+            false,
+        );
+
+        let (luma, chroma) = dst.split_at(56);
+        let luma_rows = luma
+            .chunks(8)
+            .map(|row| row.iter().all(|&value| value == 200))
+            .collect::<Vec<_>>();
+        assert_eq!(luma_rows, [false, true, true, true, true, false, false]);
+        let chroma_rows = chroma[..16]
+            .chunks(4)
+            .map(|row| row.iter().all(|&value| value == 50))
+            .collect::<Vec<_>>();
+        assert_eq!(chroma_rows, [true, true, true, false]);
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+/// Urgency of a decode. Waiting decodes are started most urgent first.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// A frame someone is looking at right now (scrubbing, playback).
+    #[default]
+    Interactive,
+    /// Frames for a render worker. Render sessions share their slots evenly.
+    Render,
+    /// Frames requested ahead of time.
+    Prefetch,
+    /// Work nobody waits on, such as proxy generation.
+    Background,
+}
+
+/// A permit to run one ffmpeg process (or in-process decoder). Released on drop.
+#[derive(Debug)]
+pub struct Slot {
+    priority: Priority,
+    session_id: u64,
+    armed: bool,
+}
+
+impl Slot {
+    /// A more urgent decode, or a render session with a smaller share, is waiting for this
+    /// slot. Long-running holders check this between frames and step aside.
+    pub fn should_yield(&self) -> bool {
+        STATE
+            .lock()
+            .unwrap()
+            .outranked(self.priority, self.session_id)
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if self.armed {
+            let mut state = STATE.lock().unwrap();
+            state.untake(self.session_id);
+            state.grant();
+        }
+    }
+}
+
+struct Waiter {
+    priority: Priority,
+    session_id: u64,
+    seq: u64,
+    grant: oneshot::Sender<Slot>,
+}
+
+#[derive(Default)]
+struct State {
+    running: usize,
+    /// Slots held per session.
+    held: HashMap<u64, usize>,
+    waiters: Vec<Waiter>,
+    next_seq: u64,
+}
+
+impl State {
+    fn held(&self, session_id: u64) -> usize {
+        self.held.get(&session_id).copied().unwrap_or(0)
+    }
+
+    fn take(&mut self, priority: Priority, session_id: u64) -> Slot {
+        self.running += 1;
+        *self.held.entry(session_id).or_insert(0) += 1;
+        Slot {
+            priority,
+            session_id,
+            armed: true,
+        }
+    }
+
+    fn untake(&mut self, session_id: u64) {
+        self.running = self.running.saturating_sub(1);
+        if let Some(held) = self.held.get_mut(&session_id) {
+            *held -= 1;
+            if *held == 0 {
+                self.held.remove(&session_id);
+            }
+        }
+    }
+
+    /// Hand free slots to waiters: most urgent first, then the session holding the fewest.
+    fn grant(&mut self) {
+        while self.running < max_processes() {
+            self.waiters.retain(|waiter| !waiter.grant.is_closed());
+            let Some(best) = (0..self.waiters.len()).min_by_key(|&index| {
+                let waiter = &self.waiters[index];
+                (waiter.priority, self.held(waiter.session_id), waiter.seq)
+            }) else {
+                break;
+            };
+            let waiter = self.waiters.swap_remove(best);
+            let slot = self.take(waiter.priority, waiter.session_id);
+            if let Err(mut slot) = waiter.grant.send(slot) {
+                // Gave up waiting after all.
+                slot.armed = false;
+                self.untake(waiter.session_id);
+            }
+        }
+    }
+
+    fn outranked(&self, priority: Priority, session_id: u64) -> bool {
+        let held = self.held(session_id);
+        self.waiters
+            .iter()
+            .filter(|waiter| !waiter.grant.is_closed())
+            .any(|waiter| {
+                waiter.priority < priority
+                    || (waiter.priority == priority
+                        && waiter.session_id != session_id
+                        && self.held(waiter.session_id) + 1 < held)
+            })
+    }
+}
+
+static STATE: LazyLock<Mutex<State>> = LazyLock::new(|| Mutex::new(State::default()));
+static MAX_PROCESSES: LazyLock<AtomicUsize> = LazyLock::new(|| {
+    let max = std::env::var("FRAMESCRIPT_MAX_FFMPEG")
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|max| *max > 0)
+        .unwrap_or_else(default_max_processes);
+    AtomicUsize::new(max)
+});
+
+/// Two processes per core: most streams spend their time blocked on a full pipe.
+fn default_max_processes() -> usize {
+    let cores = std::thread::available_parallelism().map_or(4, |cores| cores.get());
+    (cores * 2).clamp(4, 64)
+}
+
+pub fn max_processes() -> usize {
+    MAX_PROCESSES.load(Ordering::Relaxed)
+}
+
+/// Change the cap. Lowering it does not stop running processes; they yield as they idle.
+pub fn set_max_processes(max: usize) {
+    MAX_PROCESSES.store(max.max(1), Ordering::Relaxed);
+    STATE.lock().unwrap().grant();
+}
+
+/// Wait for a slot. Dropping the future gives up the place in the queue.
+pub async fn acquire(priority: Priority, session_id: u64) -> Slot {
+    let receiver = {
+        let mut state = STATE.lock().unwrap();
+        if state.running < max_processes() && state.waiters.is_empty() {
+            return state.take(priority, session_id);
+        }
+        let (grant, receiver) = oneshot::channel();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.waiters.push(Waiter {
+            priority,
+            session_id,
+            seq,
+            grant,
+        });
+        receiver
+    };
+    receiver.await.expect("scheduler dropped a waiter")
+}
+
+/// Someone is waiting for a slot. Idle streams give theirs up when this is true.
+pub fn has_waiters() -> bool {
+    STATE
+        .lock()
+        .unwrap()
+        .waiters
+        .iter()
+        .any(|waiter| !waiter.grant.is_closed())
+}
+
+/// Slots held by one session, as reported by `GET /scheduler`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSlots {
+    #[serde(rename = "sessionId")]
+    pub session_id: u64,
+    pub running: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchedulerStatus {
+    #[serde(rename = "maxProcesses")]
+    pub max_processes: usize,
+    pub running: usize,
+    /// Waiting decodes by priority.
+    pub waiting: BTreeMap<Priority, usize>,
+    pub sessions: Vec<SessionSlots>,
+}
+
+pub fn status() -> SchedulerStatus {
+    let state = STATE.lock().unwrap();
+    let mut waiting = BTreeMap::new();
+    for waiter in state
+        .waiters
+        .iter()
+        .filter(|waiter| !waiter.grant.is_closed())
+    {
+        *waiting.entry(waiter.priority).or_insert(0) += 1;
+    }
+    let mut sessions = state
+        .held
+        .iter()
+        .map(|(&session_id, &running)| SessionSlots {
+            session_id,
+            running,
+        })
+        .collect::<Vec<_>>();
+    sessions.sort_by_key(|session| session.session_id);
+    SchedulerStatus {
+        max_processes: max_processes(),
+        running: state.running,
+        waiting,
+        sessions,
+    }
+}
@@ -0,0 +1,249 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::util::KeyFloat;
+
+/// Frame rate ffmpeg's image2 demuxer assumes when none is given.
+const DEFAULT_SEQUENCE_FPS: f64 = 25.0;
+/// Numbers ffmpeg tries, from 0, when looking for the first file of a sequence.
+const START_NUMBER_SEARCH: u32 = 5;
+
+/// Numbering and timing of a `shot_%04d.png` style image sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+pub struct ImageSequence {
+    /// Number of the first file. Looked up on disk when omitted.
+    #[serde(rename = "startNumber")]
+    pub start_number: Option<u32>,
+    pub fps: Option<KeyFloat>,
+}
+
+impl ImageSequence {
+    /// Fill in the start number and frame rate for `pattern`.
+    pub fn resolve(self, pattern: &str) -> Self {
+        let start_number = self.start_number.unwrap_or_else(|| {
+            (0..START_NUMBER_SEARCH)
+                .find(|&number| Path::new(&sequence_file(pattern, number)).is_file())
+                .unwrap_or(0)
+        });
+        let fps = self
+            .fps
+            .map(|fps| fps.0)
+            .filter(|fps| fps.is_finite() && *fps > 0.0)
+            .unwrap_or(DEFAULT_SEQUENCE_FPS);
+        Self {
+            start_number: Some(start_number),
+            fps: Some(KeyFloat(fps)),
+        }
+    }
+
+    pub fn start(&self) -> u32 {
+        self.start_number.unwrap_or(0)
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps.map_or(DEFAULT_SEQUENCE_FPS, |fps| fps.0)
+    }
+
+    /// Path of the first file, used to probe size, codec and color.
+    pub fn first_file(&self, pattern: &str) -> String {
+        sequence_file(pattern, self.start())
+    }
+
+    /// Number of consecutive files on disk from the start number.
+    pub fn frame_count(&self, pattern: &str) -> u32 {
+        let start = self.start();
+        let mut count = 0;
+        while Path::new(&sequence_file(pattern, start.saturating_add(count))).is_file() {
+            count += 1;
+            if start.checked_add(count).is_none() {
+                break;
+            }
+        }
+        count
+    }
+}
+
+/// Position of the `%d` / `%0Nd` placeholder in `pattern` as (start, end, width).
+fn placeholder(pattern: &str) -> Option<(usize, usize, usize)> {
+    let bytes = pattern.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] != b'%' {
+            index += 1;
+            continue;
+        }
+        if bytes.get(index + 1) == Some(&b'%') {
+            index += 2;
+            continue;
+        }
+
+        let digits = bytes[index + 1..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count();
+        let end = index + 1 + digits;
+        if bytes.get(end) == Some(&b'd') {
+            let width = pattern[index + 1..end].parse().unwrap_or(0);
+            return Some((index, end + 1, width));
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Whether `path` is a printf-style sequence pattern such as `shot_%04d.png`. A file that
+/// exists under that very name, e.g. `50%done.mp4`, is a file rather than a pattern.
+pub fn is_sequence_pattern(path: &str) -> bool {
+    placeholder(path).is_some() && !Path::new(path).is_file()
+}
+
+/// File name of frame `number` of a sequence.
+pub fn sequence_file(pattern: &str, number: u32) -> String {
+    let Some((start, end, width)) = placeholder(pattern) else {
+        return pattern.to_string();
+    };
+    format!(
+        "{}{:0width$}{}",
+        pattern[..start].replace("%%", "%"),
+        number,
+        pattern[end..].replace("%%", "%"),
+    )
+}
+
+/// Frame number of `file` when it belongs to the sequence `pattern`.
+pub fn sequence_number(pattern: &str, file: &str) -> Option<u32> {
+    let (start, end, width) = placeholder(pattern)?;
+    let prefix = pattern[..start].replace("%%", "%");
+    let suffix = pattern[end..].replace("%%", "%");
+    let digits = file.strip_prefix(&prefix)?.strip_suffix(&suffix)?;
+    if digits.len() < width.max(1) || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    // `%04d` pads to four digits, so longer numbers never start with a zero.
+    if digits.len() > width.max(1) && digits.starts_with('0') {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Whether a stream of `codec` may be an animated image, depending on its frame count.
+pub fn is_image_codec(codec: &str) -> bool {
+    matches!(codec, "gif" | "png" | "apng" | "webp")
+}
+
+/// Whether a stream is an animated image (GIF, APNG, animated WebP) rather than a video
+/// or a still. ffmpeg only picks the `apng` codec for PNGs with an animation.
+///
+/// These formats cannot be seeked, so the decoder reads them from the first frame.
+pub fn is_animated_image(codec: &str, frame_count: u32) -> bool {
+    codec == "apng" || (is_image_codec(codec) && frame_count > 1)
+}
+
+/// Whether `path` is an OpenEXR file, which holds linear light.
+pub fn is_exr(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"))
+}
+
+/// Resolved sequence settings when `path` is a sequence pattern, `None` otherwise.
+pub fn sequence_for(path: &str, options: Option<ImageSequence>) -> Option<ImageSequence> {
+    is_sequence_pattern(path).then(|| options.unwrap_or_default().resolve(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Empty directory under the system temp dir, unique to this test.
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("framescript-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn finds_the_placeholder() {
+        assert_eq!(placeholder("shot_%04d.png"), Some((5, 9, 4)));
+        assert_eq!(placeholder("shot_%d.png"), Some((5, 7, 0)));
+        assert_eq!(placeholder("100%%_%03d.png"), Some((6, 10, 3)));
+        assert_eq!(placeholder("100%%.png"), None);
+        assert_eq!(placeholder("50%done.mp4"), Some((2, 4, 0)));
+        assert_eq!(placeholder("clip.mp4"), None);
+    }
+
+    #[test]
+    fn formats_sequence_files() {
+        assert_eq!(sequence_file("shot_%04d.png", 7), "shot_0007.png");
+        assert_eq!(sequence_file("shot_%04d.png", 12345), "shot_12345.png");
+        assert_eq!(sequence_file("shot_%d.png", 7), "shot_7.png");
+        assert_eq!(sequence_file("100%%_%02d.png", 3), "100%_03.png");
+        assert_eq!(sequence_file("clip.mp4", 3), "clip.mp4");
+    }
+
+    #[test]
+    fn numbers_files_of_a_sequence() {
+        assert_eq!(sequence_number("shot_%04d.png", "shot_0007.png"), Some(7));
+        assert_eq!(
+            sequence_number("shot_%04d.png", "shot_12345.png"),
+            Some(12345)
+        );
+        assert_eq!(sequence_number("shot_%d.png", "shot_7.png"), Some(7));
+        assert_eq!(sequence_number("100%%_%02d.png", "100%_03.png"), Some(3));
+        assert_eq!(sequence_number("shot_%04d.png", "shot_007.png"), None);
+        assert_eq!(sequence_number("shot_%04d.png", "shot_00007.png"), None);
+        assert_eq!(sequence_number("shot_%04d.png", "shot_000a.png"), None);
+        assert_eq!(sequence_number("shot_%04d.png", "take_0007.png"), None);
+        assert_eq!(sequence_number("clip.mp4", "clip.mp4"), None);
+    }
+
+    #[test]
+    fn existing_files_are_not_patterns() {
+        let dir = temp_dir("pattern");
+        let file = dir.join("50%2d.mp4");
+        let pattern = file.to_string_lossy().into_owned();
+        assert!(is_sequence_pattern(&pattern));
+        fs::write(&file, b"").unwrap();
+        assert!(!is_sequence_pattern(&pattern));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_sequences_on_disk() {
+        let dir = temp_dir("sequence");
+        for number in 2..5 {
+            fs::write(dir.join(format!("shot_{number:03}.png")), b"").unwrap();
+        }
+        let pattern = dir.join("shot_%03d.png").to_string_lossy().into_owned();
+
+        let sequence = ImageSequence::default().resolve(&pattern);
+        assert_eq!(sequence.start(), 2);
+        assert_eq!(sequence.fps(), DEFAULT_SEQUENCE_FPS);
+        assert_eq!(sequence.frame_count(&pattern), 3);
+        assert!(sequence.first_file(&pattern).ends_with("shot_002.png"));
+
+        let explicit = ImageSequence {
+            start_number: Some(3),
+            fps: Some(KeyFloat(-1.0)),
+        }
+        .resolve(&pattern);
+        assert_eq!(explicit.frame_count(&pattern), 2);
+        assert_eq!(explicit.fps(), DEFAULT_SEQUENCE_FPS);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn detects_animated_images() {
+        assert!(is_animated_image("apng", 0));
+        assert!(is_animated_image("gif", 12));
+        assert!(is_animated_image("webp", 2));
+        assert!(!is_animated_image("gif", 1));
+        assert!(!is_animated_image("png", 1));
+        assert!(!is_animated_image("h264", 240));
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    fs::{self, Metadata},
+    path::Path,
+    sync::{LazyLock, Mutex, Once},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::info;
+
+use crate::decoder::{
+    invalidate_source,
+    source::{is_sequence_pattern, sequence_number},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Identity of a file on disk: changes when the file is rewritten or replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileIdentity {
+    pub len: u64,
+    pub modified_nanos: u128,
+    /// Inode number; 0 where the platform has none.
+    pub inode: u64,
+}
+
+pub fn file_identity(path: &str) -> Option<FileIdentity> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    identity(&metadata)
+}
+
+/// Identity of what a source path stands for. A sequence pattern stands for its directory,
+/// which changes whenever a frame file is added, removed or replaced by a rename.
+fn watched_identity(path: &str) -> Option<FileIdentity> {
+    if !is_sequence_pattern(path) {
+        return file_identity(path);
+    }
+    let dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    identity(&fs::metadata(dir).ok()?)
+}
+
+fn identity(metadata: &Metadata) -> Option<FileIdentity> {
+    let modified_nanos = metadata
+        .modified()
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    #[cfg(unix)]
+    let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+    #[cfg(not(unix))]
+    let inode = 0;
+    Some(FileIdentity {
+        len: metadata.len(),
+        modified_nanos,
+        inode,
+    })
+}
+
+/// Pushed to every `/ws` client when a source in use changes on disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaChanged {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub path: String,
+    /// The file no longer exists.
+    pub removed: bool,
+}
+
+/// Sources that have been opened or probed, with the identity they were read at.
+/// `None` once a watched file has disappeared, so that its return is noticed too.
+static WATCHED: LazyLock<Mutex<HashMap<String, Option<FileIdentity>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static EVENTS: LazyLock<broadcast::Sender<MediaChanged>> =
+    LazyLock::new(|| broadcast::channel(64).0);
+static WATCHER: Once = Once::new();
+
+/// Receive a [`MediaChanged`] for every source that changes from now on.
+pub fn subscribe() -> broadcast::Receiver<MediaChanged> {
+    EVENTS.subscribe()
+}
+
+/// Start watching `path`, a file or a sequence pattern, for changes. Paths that do not
+/// exist are ignored. The file system is read on a blocking thread.
+pub(super) fn track(path: &str) {
+    if WATCHED.lock().unwrap().contains_key(path) {
+        return;
+    }
+    let path = path.to_string();
+    tokio::task::spawn_blocking(move || {
+        let Some(identity) = watched_identity(&path) else {
+            return;
+        };
+        WATCHED
+            .lock()
+            .unwrap()
+            .entry(path)
+            .or_insert(Some(identity));
+    });
+    ensure_watcher();
+}
+
+/// Watched sequence patterns that `path` is a frame of.
+fn sequences_containing(path: &str) -> Vec<String> {
+    WATCHED
+        .lock()
+        .unwrap()
+        .keys()
+        .filter(|pattern| *pattern != path && sequence_number(pattern, path).is_some())
+        .cloned()
+        .collect()
+}
+
+/// Stop watching everything. Called when every decoder is dropped.
+pub(super) fn reset() {
+    WATCHED.lock().unwrap().clear();
+}
+
+/// Poll the watched files. Polling rather than OS notifications also catches sources on
+/// network shares and editors that replace files by renaming over them.
+fn ensure_watcher() {
+    WATCHER.call_once(|| {
+        tokio::spawn(async {
+            loop {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                let Ok(changed) = tokio::task::spawn_blocking(changed_files).await else {
+                    continue;
+                };
+                for (file, removed) in changed {
+                    // Decoders of a sequence are keyed by its pattern, not its files.
+                    let sequences = sequences_containing(&file);
+                    let sources = std::iter::once((file, removed))
+                        .chain(sequences.into_iter().map(|pattern| (pattern, false)));
+                    for (path, removed) in sources {
+                        let decoders = invalidate_source(&path);
+                        info!("{path} changed on disk, dropped {decoders} decoders");
+                        let _ = EVENTS.send(MediaChanged {
+                            kind: "media-changed",
+                            path,
+                            removed,
+                        });
+                    }
+                }
+            }
+        });
+    });
+}
+
+/// Watched files whose identity changed since the last poll, and whether they are gone.
+fn changed_files() -> Vec<(String, bool)> {
+    let paths = WATCHED
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(path, identity)| (path.clone(), *identity))
+        .collect::<Vec<_>>();
+
+    let mut changed = Vec::new();
+    for (path, previous) in paths {
+        let current = watched_identity(&path);
+        if current == previous {
+            continue;
+        }
+        if let Some(identity) = WATCHED.lock().unwrap().get_mut(&path) {
+            *identity = current;
+        }
+        changed.push((path, current.is_none()));
+    }
+    changed
+}
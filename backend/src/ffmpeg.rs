@@ -1,10 +1,12 @@
+pub mod capabilities;
 pub mod hw_decoder;
+pub mod hwaccel;
 pub mod sw_decoder;
 pub(crate) mod command;
 pub(crate) mod bin;
 
-use serde::Deserialize;
-use std::process::Command;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, process::Command};
 
 #[derive(Debug, Deserialize)]
 struct FfprobeFormat {
@@ -13,19 +15,61 @@ struct FfprobeFormat {
 
 #[derive(Debug, Deserialize)]
 struct FfprobeStream {
+    index: Option<u32>,
+    codec_type: Option<String>,
+    channels: Option<u32>,
+    channel_layout: Option<String>,
+    disposition: Option<FfprobeDisposition>,
     duration: Option<String>,
+    start_time: Option<String>,
     avg_frame_rate: Option<String>,
     r_frame_rate: Option<String>,
     nb_read_frames: Option<String>,
     nb_frames: Option<String>,
+    codec_name: Option<String>,
     width: Option<u32>,
     height: Option<u32>,
+    pix_fmt: Option<String>,
+    color_space: Option<String>,
+    color_range: Option<String>,
+    color_primaries: Option<String>,
+    color_transfer: Option<String>,
+    sample_aspect_ratio: Option<String>,
+    field_order: Option<String>,
+    side_data_list: Option<Vec<FfprobeSideData>>,
+    tags: Option<FfprobeTags>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeSideData {
+    rotation: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeTags {
+    rotate: Option<String>,
+    alpha_mode: Option<String>,
+    language: Option<String>,
+    title: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeDisposition {
+    default: Option<u8>,
+    attached_pic: Option<u8>,
 }
 
 #[derive(Debug, Deserialize)]
 struct FfprobeOutput {
     format: Option<FfprobeFormat>,
     streams: Option<Vec<FfprobeStream>>,
+    packets: Option<Vec<FfprobePacket>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobePacket {
+    pts_time: Option<String>,
+    flags: Option<String>,
 }
 
 fn run_ffprobe(
@@ -33,6 +77,17 @@ fn run_ffprobe(
     select_streams: Option<&str>,
     entries: &str,
     count_frames: bool,
+) -> Result<FfprobeOutput, String> {
+    run_ffprobe_interval(path, select_streams, entries, count_frames, None)
+}
+
+/// Like [`run_ffprobe`], reading only packets within `read_interval` (ffprobe syntax).
+fn run_ffprobe_interval(
+    path: &str,
+    select_streams: Option<&str>,
+    entries: &str,
+    count_frames: bool,
+    read_interval: Option<&str>,
 ) -> Result<FfprobeOutput, String> {
     let ffprobe = bin::ffprobe_path()?;
     let mut cmd = Command::new(ffprobe);
@@ -48,6 +103,9 @@ fn run_ffprobe(
     if let Some(select_streams) = select_streams {
         cmd.arg("-select_streams").arg(select_streams);
     }
+    if let Some(read_interval) = read_interval {
+        cmd.arg("-read_intervals").arg(read_interval);
+    }
     cmd.arg(path);
 
     let output = cmd
@@ -103,8 +161,8 @@ fn parse_ratio(value: Option<&str>) -> Option<f64> {
 }
 
 /// Return video duration in milliseconds using ffprobe metadata.
-pub fn probe_video_duration_ms(path: &str) -> Result<u64, String> {
-    let output = run_ffprobe(path, Some("v:0"), "format=duration:stream=duration", false)?;
+pub fn probe_video_duration_ms(path: &str, stream: &str) -> Result<u64, String> {
+    let output = run_ffprobe(path, Some(stream), "format=duration:stream=duration", false)?;
     let stream_duration = output
         .streams
         .as_ref()
@@ -119,10 +177,75 @@ pub fn probe_video_duration_ms(path: &str) -> Result<u64, String> {
     Ok((seconds * 1000.0).round().max(0.0) as u64)
 }
 
-pub fn probe_video_frames(path: &str) -> Result<u64, String> {
+/// Keyframe times within `from..from + span` seconds, counted from the start of the stream.
+/// Only that part of the file is read.
+pub fn probe_keyframes(path: &str, stream: &str, from: f64, span: f64) -> Result<Vec<f64>, String> {
+    let start_time = run_ffprobe(path, Some(stream), "stream=start_time", false)?
+        .streams
+        .as_ref()
+        .and_then(|streams| streams.first())
+        .and_then(|stream| stream.start_time.as_deref()?.parse::<f64>().ok())
+        .filter(|start| start.is_finite())
+        .unwrap_or(0.0);
+
+    let interval = format!("{:.6}%+{:.6}", start_time + from.max(0.0), span.max(0.0));
+    let output = run_ffprobe_interval(
+        path,
+        Some(stream),
+        "packet=pts_time,flags",
+        false,
+        Some(&interval),
+    )?;
+    let mut keyframes = output
+        .packets
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|packet| packet.flags.as_deref().unwrap_or_default().contains('K'))
+        .filter_map(|packet| packet.pts_time?.parse::<f64>().ok())
+        .map(|time| time - start_time)
+        .collect::<Vec<_>>();
+    keyframes.sort_by(f64::total_cmp);
+    Ok(keyframes)
+}
+
+/// Frame count from the container: `nb_frames`, or the duration times the frame rate.
+/// Cheap, unlike [`probe_video_frames`], but some formats carry neither.
+pub fn estimate_video_frames(path: &str, stream: &str) -> Result<u64, String> {
     let output = run_ffprobe(
         path,
-        Some("v:0"),
+        Some(stream),
+        "stream=nb_frames,duration,avg_frame_rate",
+        false,
+    )?;
+    let stream = output
+        .streams
+        .as_ref()
+        .and_then(|streams| streams.first())
+        .ok_or_else(|| "failed to read frames".to_string())?;
+
+    if let Some(frames) = stream
+        .nb_frames
+        .as_deref()
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|&frames| frames > 0)
+    {
+        return Ok(frames);
+    }
+
+    let duration = parse_duration_seconds(stream.duration.as_deref());
+    let fps = parse_ratio(stream.avg_frame_rate.as_deref());
+    match (duration, fps) {
+        (Some(duration), Some(fps)) if duration * fps >= 1.0 => Ok((duration * fps).round() as u64),
+        _ => Err("container has no frame count".to_string()),
+    }
+}
+
+/// Frame count, decoding the whole stream to count its frames when the container does not
+/// say.
+pub fn probe_video_frames(path: &str, stream: &str) -> Result<u64, String> {
+    let output = run_ffprobe(
+        path,
+        Some(stream),
         "stream=nb_read_frames,nb_frames,duration,avg_frame_rate",
         true,
     )?;
@@ -157,8 +280,13 @@ pub fn probe_video_frames(path: &str) -> Result<u64, String> {
     Err("failed to read frames".to_string())
 }
 
-pub fn probe_video_fps(path: &str) -> Result<f64, String> {
-    let output = run_ffprobe(path, Some("v:0"), "stream=avg_frame_rate,r_frame_rate", false)?;
+pub fn probe_video_fps(path: &str, stream: &str) -> Result<f64, String> {
+    let output = run_ffprobe(
+        path,
+        Some(stream),
+        "stream=avg_frame_rate,r_frame_rate",
+        false,
+    )?;
     let stream = output
         .streams
         .as_ref()
@@ -172,8 +300,59 @@ pub fn probe_video_fps(path: &str) -> Result<f64, String> {
     Ok(fps)
 }
 
-pub fn probe_video_dimensions(path: &str) -> Result<(u32, u32), String> {
-    let output = run_ffprobe(path, Some("v:0"), "stream=width,height", false)?;
+/// Coded size of a video stream plus what a player applies on top of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoGeometry {
+    pub coded_width: u32,
+    pub coded_height: u32,
+    /// Sample (pixel) aspect ratio as `(num, den)`. `(1, 1)` for square pixels.
+    pub sample_aspect_ratio: (u32, u32),
+    /// Clockwise display rotation in degrees: 0, 90, 180 or 270.
+    pub rotation: u32,
+}
+
+impl VideoGeometry {
+    /// Size before rotation with non-square pixels stretched to square ones.
+    pub fn unrotated_dimensions(&self) -> (u32, u32) {
+        let (num, den) = self.sample_aspect_ratio;
+        if num == 0 || den == 0 || num == den {
+            return (self.coded_width, self.coded_height);
+        }
+        let width = (self.coded_width as f64 * num as f64 / den as f64).round() as u32;
+        (width.max(1), self.coded_height)
+    }
+
+    /// Size a media player shows the video at.
+    pub fn display_dimensions(&self) -> (u32, u32) {
+        let (width, height) = self.unrotated_dimensions();
+        if self.rotation % 180 == 90 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+}
+
+fn parse_sample_aspect_ratio(value: Option<&str>) -> (u32, u32) {
+    value
+        .and_then(|value| value.split_once(':'))
+        .and_then(|(num, den)| Some((num.parse::<u32>().ok()?, den.parse::<u32>().ok()?)))
+        .filter(|(num, den)| *num > 0 && *den > 0)
+        .unwrap_or((1, 1))
+}
+
+fn normalize_rotation(degrees: f64) -> u32 {
+    let quarter_turns = (degrees / 90.0).round() as i64;
+    (quarter_turns.rem_euclid(4) * 90) as u32
+}
+
+pub fn probe_video_geometry(path: &str, stream: &str) -> Result<VideoGeometry, String> {
+    let output = run_ffprobe(
+        path,
+        Some(stream),
+        "stream=width,height,sample_aspect_ratio:stream_side_data=rotation:stream_tags=rotate",
+        false,
+    )?;
     let stream = output
         .streams
         .as_ref()
@@ -182,19 +361,277 @@ pub fn probe_video_dimensions(path: &str) -> Result<(u32, u32), String> {
 
     let width = stream.width.unwrap_or(0);
     let height = stream.height.unwrap_or(0);
-    if width > 0 && height > 0 {
-        Ok((width, height))
-    } else {
-        Err("failed to read dimensions".to_string())
+    if width == 0 || height == 0 {
+        return Err("failed to read dimensions".to_string());
+    }
+
+    // The display matrix stores a counter-clockwise angle; the legacy `rotate` tag is clockwise.
+    let matrix_rotation = stream
+        .side_data_list
+        .iter()
+        .flatten()
+        .find_map(|side_data| side_data.rotation)
+        .map(|degrees| -degrees);
+    let tag_rotation = stream
+        .tags
+        .as_ref()
+        .and_then(|tags| tags.rotate.as_deref())
+        .and_then(|value| value.trim().parse::<f64>().ok());
+
+    Ok(VideoGeometry {
+        coded_width: width,
+        coded_height: height,
+        sample_aspect_ratio: parse_sample_aspect_ratio(stream.sample_aspect_ratio.as_deref()),
+        rotation: normalize_rotation(matrix_rotation.or(tag_rotation).unwrap_or(0.0)),
+    })
+}
+
+/// Return the displayed (rotated, square-pixel) size of a video.
+pub fn probe_video_dimensions(path: &str, stream: &str) -> Result<(u32, u32), String> {
+    probe_video_geometry(path, stream).map(|geometry| geometry.display_dimensions())
+}
+
+/// Color description of a video stream as reported by ffprobe.
+/// Fields are `None` when the container leaves them unspecified.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VideoColorInfo {
+    pub pix_fmt: Option<String>,
+    pub color_space: Option<String>,
+    pub color_range: Option<String>,
+    pub color_primaries: Option<String>,
+    pub color_transfer: Option<String>,
+}
+
+fn known_value(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.is_empty() && value != "unknown" && value != "unspecified")
+}
+
+pub fn probe_video_color(path: &str, stream: &str) -> Result<VideoColorInfo, String> {
+    let output = run_ffprobe(
+        path,
+        Some(stream),
+        "stream=pix_fmt,color_space,color_range,color_primaries,color_transfer",
+        false,
+    )?;
+    let stream = output
+        .streams
+        .and_then(|streams| streams.into_iter().next())
+        .ok_or_else(|| "Not video!".to_string())?;
+
+    Ok(VideoColorInfo {
+        pix_fmt: known_value(stream.pix_fmt),
+        color_space: known_value(stream.color_space),
+        color_range: known_value(stream.color_range),
+        color_primaries: known_value(stream.color_primaries),
+        color_transfer: known_value(stream.color_transfer),
+    })
+}
+
+/// How the fields of a video stream are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldOrder {
+    #[default]
+    Progressive,
+    /// Interlaced, top field first.
+    TopFirst,
+    /// Interlaced, bottom field first.
+    BottomFirst,
+}
+
+impl FieldOrder {
+    pub fn is_interlaced(self) -> bool {
+        self != FieldOrder::Progressive
     }
 }
 
+pub fn probe_video_field_order(path: &str, stream: &str) -> Result<FieldOrder, String> {
+    let output = run_ffprobe(path, Some(stream), "stream=field_order", false)?;
+    let stream = output
+        .streams
+        .and_then(|streams| streams.into_iter().next())
+        .ok_or_else(|| "Not video!".to_string())?;
+
+    // `tb`/`bt` name the coded order first; the displayed (second) field is what counts.
+    Ok(match stream.field_order.as_deref() {
+        Some("tt" | "bt") => FieldOrder::TopFirst,
+        Some("bb" | "tb") => FieldOrder::BottomFirst,
+        _ => FieldOrder::Progressive,
+    })
+}
+
+/// Codec of a video stream and whether it carries an alpha channel.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VideoCodecInfo {
+    pub codec_name: Option<String>,
+    pub pix_fmt: Option<String>,
+    pub has_alpha: bool,
+}
+
+impl VideoCodecInfo {
+    /// Decoder that keeps the alpha plane, when ffmpeg's default one would drop it.
+    pub fn alpha_decoder(&self) -> Option<&'static str> {
+        if !self.has_alpha {
+            return None;
+        }
+        match self.codec_name.as_deref() {
+            // The native VP8/VP9 decoders ignore the alpha side channel of WebM.
+            Some("vp9") => Some("libvpx-vp9"),
+            Some("vp8") => Some("libvpx"),
+            _ => None,
+        }
+    }
+}
+
+fn pix_fmt_has_alpha(pix_fmt: &str) -> bool {
+    pix_fmt.starts_with("yuva")
+        || pix_fmt.starts_with("gbrap")
+        || pix_fmt.starts_with("ya")
+        || pix_fmt.starts_with("rgba")
+        || pix_fmt.starts_with("bgra")
+        || pix_fmt.starts_with("argb")
+        || pix_fmt.starts_with("abgr")
+        || pix_fmt.starts_with("ayuv")
+        || pix_fmt == "pal8"
+}
+
+pub fn probe_video_codec(path: &str, stream: &str) -> Result<VideoCodecInfo, String> {
+    let output = run_ffprobe(
+        path,
+        Some(stream),
+        "stream=codec_name,pix_fmt:stream_tags=alpha_mode",
+        false,
+    )?;
+    let stream = output
+        .streams
+        .and_then(|streams| streams.into_iter().next())
+        .ok_or_else(|| "Not video!".to_string())?;
+
+    // VP8/VP9 in WebM store alpha separately; the stream pix_fmt then reads `yuv420p`.
+    let alpha_mode = stream
+        .tags
+        .as_ref()
+        .and_then(|tags| tags.alpha_mode.as_deref())
+        .is_some_and(|mode| mode.trim() == "1");
+    let pix_fmt = known_value(stream.pix_fmt);
+    let has_alpha = alpha_mode || pix_fmt.as_deref().is_some_and(pix_fmt_has_alpha);
+
+    Ok(VideoCodecInfo {
+        codec_name: known_value(stream.codec_name),
+        pix_fmt,
+        has_alpha,
+    })
+}
+
+/// Picks one stream of a kind, by its position among streams of that kind or by its
+/// language tag. The first stream is used when neither is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StreamSelector {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    /// ISO 639 code as tagged in the container (e.g. `eng`, `jpn`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+impl StreamSelector {
+    /// ffmpeg stream specifier for `kind` (`v` or `a`). An index wins over a language.
+    pub fn specifier(&self, kind: char) -> String {
+        if let Some(index) = self.index {
+            return format!("{kind}:{index}");
+        }
+        match self.language.as_deref().map(str::trim) {
+            Some(language)
+                if !language.is_empty()
+                    && language
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-') =>
+            {
+                format!("{kind}:m:language:{language}")
+            }
+            _ => format!("{kind}:0"),
+        }
+    }
+}
+
+/// One stream of a media file, as listed by [`probe_streams`].
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamInfo {
+    /// Index among all streams of the file.
+    pub index: u32,
+    /// `video`, `audio`, `subtitle`, ...
+    pub kind: String,
+    /// Index among streams of the same kind, as used by [`StreamSelector::index`].
+    pub kind_index: u32,
+    pub codec_name: Option<String>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub channels: Option<u32>,
+    pub channel_layout: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub default: bool,
+    /// Cover art stored as a video stream.
+    pub attached_pic: bool,
+}
+
+/// List the streams of a file so that clients can offer a choice.
+pub fn probe_streams(path: &str) -> Result<Vec<StreamInfo>, String> {
+    let output = run_ffprobe(
+        path,
+        None,
+        "stream=index,codec_type,codec_name,channels,channel_layout,width,height:stream_tags=language,title:stream_disposition=default,attached_pic",
+        false,
+    )?;
+
+    let mut kind_counts: HashMap<String, u32> = HashMap::new();
+    let streams = output
+        .streams
+        .unwrap_or_default()
+        .into_iter()
+        .map(|stream| {
+            let kind = stream
+                .codec_type
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string());
+            let kind_index = kind_counts.entry(kind.clone()).or_insert(0);
+            let info = StreamInfo {
+                index: stream.index.unwrap_or(0),
+                kind,
+                kind_index: *kind_index,
+                codec_name: known_value(stream.codec_name),
+                language: stream
+                    .tags
+                    .as_ref()
+                    .and_then(|tags| known_value(tags.language.clone())),
+                title: stream.tags.as_ref().and_then(|tags| tags.title.clone()),
+                channels: stream.channels,
+                channel_layout: known_value(stream.channel_layout),
+                width: stream.width,
+                height: stream.height,
+                default: stream
+                    .disposition
+                    .as_ref()
+                    .and_then(|disposition| disposition.default)
+                    == Some(1),
+                attached_pic: stream
+                    .disposition
+                    .as_ref()
+                    .and_then(|disposition| disposition.attached_pic)
+                    == Some(1),
+            };
+            *kind_index += 1;
+            info
+        })
+        .collect();
+    Ok(streams)
+}
+
 /// Return audio duration in milliseconds using ffprobe metadata.
-pub fn probe_audio_duration_ms(path: &str) -> Result<u64, String> {
+pub fn probe_audio_duration_ms(path: &str, stream: &str) -> Result<u64, String> {
     // Some containers report bogus global duration; prefer audio stream duration when available.
     const MAX_REASONABLE_DURATION_MS: u64 = 1000 * 60 * 60 * 24 * 7; // 7 days
 
-    let output = run_ffprobe(path, Some("a:0"), "format=duration:stream=duration", false)?;
+    let output = run_ffprobe(path, Some(stream), "format=duration:stream=duration", false)?;
     let stream_duration = output
         .streams
         .as_ref()
@@ -214,3 +651,41 @@ pub fn probe_audio_duration_ms(path: &str) -> Result<u64, String> {
 
     Err("failed to read audio duration".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector(index: Option<u32>, language: Option<&str>) -> StreamSelector {
+        StreamSelector {
+            index,
+            language: language.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn stream_selector_defaults_to_the_first_stream() {
+        assert_eq!(StreamSelector::default().specifier('v'), "v:0");
+        assert_eq!(StreamSelector::default().specifier('a'), "a:0");
+    }
+
+    #[test]
+    fn stream_selector_prefers_the_index() {
+        assert_eq!(selector(Some(2), None).specifier('a'), "a:2");
+        assert_eq!(selector(Some(1), Some("jpn")).specifier('v'), "v:1");
+    }
+
+    #[test]
+    fn stream_selector_matches_languages() {
+        assert_eq!(
+            selector(None, Some(" eng ")).specifier('a'),
+            "a:m:language:eng"
+        );
+        assert_eq!(
+            selector(None, Some("pt-BR")).specifier('a'),
+            "a:m:language:pt-BR"
+        );
+        assert_eq!(selector(None, Some("")).specifier('a'), "a:0");
+        assert_eq!(selector(None, Some("en,g")).specifier('a'), "a:0");
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+
+use crate::ffmpeg::{
+    bin::{ffmpeg_path, ffprobe_path},
+    hwaccel::list_methods,
+};
+
+/// An ffmpeg tool as resolved by `bin`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Binary {
+    pub path: String,
+    /// Version from the first line of `-version`, e.g. `6.1.1` or `N-113672-g1234abcd`.
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodecKind {
+    Video,
+    Audio,
+    Subtitle,
+    Data,
+    Attachment,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Codec {
+    pub name: String,
+    pub kind: CodecKind,
+    pub description: String,
+}
+
+/// What the local ffmpeg build supports.
+#[derive(Debug, Clone, Serialize)]
+pub struct FfmpegCapabilities {
+    pub ffmpeg: Option<Binary>,
+    pub ffprobe: Option<Binary>,
+    pub decoders: Vec<Codec>,
+    pub encoders: Vec<Codec>,
+    pub hwaccels: Vec<String>,
+    pub filters: Vec<String>,
+    /// Queries that failed, e.g. because ffmpeg is missing.
+    pub errors: Vec<String>,
+}
+
+/// Kept once both tools were found; a missing ffmpeg is looked for again next time.
+static CACHED: Mutex<Option<Arc<FfmpegCapabilities>>> = Mutex::new(None);
+
+/// Query the local ffmpeg and ffprobe. Blocks while they run.
+pub fn capabilities() -> Arc<FfmpegCapabilities> {
+    if let Some(cached) = CACHED.lock().unwrap().as_ref() {
+        return cached.clone();
+    }
+    let capabilities = Arc::new(query());
+    if capabilities.ffmpeg.is_some() && capabilities.ffprobe.is_some() {
+        *CACHED.lock().unwrap() = Some(capabilities.clone());
+    }
+    capabilities
+}
+
+fn query() -> FfmpegCapabilities {
+    let mut errors = Vec::new();
+    let ffmpeg = collect(&mut errors, ffmpeg_path().map(binary));
+    let ffprobe = collect(&mut errors, ffprobe_path().map(binary));
+    let (mut decoders, mut encoders, mut hwaccels, mut filters) = Default::default();
+    if let Some(ffmpeg) = &ffmpeg {
+        let path = ffmpeg.path.as_str();
+        let codecs = |query| run(path, query).map(|out| parse_codecs(&out));
+        decoders = collect(&mut errors, codecs("-decoders")).unwrap_or_default();
+        encoders = collect(&mut errors, codecs("-encoders")).unwrap_or_default();
+        hwaccels = collect(&mut errors, list_methods(path)).unwrap_or_default();
+        let listed = run(path, "-filters").map(|out| parse_filters(&out));
+        filters = collect(&mut errors, listed).unwrap_or_default();
+    }
+    FfmpegCapabilities {
+        ffmpeg,
+        ffprobe,
+        decoders,
+        encoders,
+        hwaccels,
+        filters,
+        errors,
+    }
+}
+
+/// The value of `result`, with its error noted in `errors`.
+fn collect<T>(errors: &mut Vec<String>, result: Result<T, String>) -> Option<T> {
+    result.map_err(|error| errors.push(error)).ok()
+}
+
+fn binary(path: String) -> Binary {
+    let version = run(&path, "-version")
+        .ok()
+        .and_then(|out| parse_version(&out));
+    Binary { path, version }
+}
+
+/// Run `tool -hide_banner <query>` and return its stdout.
+fn run(tool: &str, query: &str) -> Result<String, String> {
+    let output = Command::new(tool)
+        .args(["-hide_banner", query])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| format!("failed to run {tool} {query}: {error}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{tool} {query} failed: {}", stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `ffmpeg version 6.1.1-3ubuntu5 Copyright ...` -> `6.1.1-3ubuntu5`.
+fn parse_version(output: &str) -> Option<String> {
+    let mut words = output.lines().next()?.split_whitespace();
+    words.find(|&word| word == "version")?;
+    words.next().map(str::to_string)
+}
+
+/// Rows of `-decoders`/`-encoders` below the ` ------` rule: `flags name description`,
+/// where the first flag is the media type.
+fn parse_codecs(output: &str) -> Vec<Codec> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut columns = line.trim_start().splitn(3, char::is_whitespace);
+            let flags = columns.next()?;
+            let name = columns.next()?;
+            let kind = match flags.chars().next()? {
+                'V' => CodecKind::Video,
+                'A' => CodecKind::Audio,
+                'S' => CodecKind::Subtitle,
+                'D' => CodecKind::Data,
+                'T' => CodecKind::Attachment,
+                _ => return None,
+            };
+            Some(Codec {
+                name: name.to_string(),
+                kind,
+                description: columns.next().unwrap_or_default().trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Rows of `-filters` are `flags name inputs->outputs description`; the legend above them
+/// has no `->` column.
+fn parse_filters(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let (_flags, name, io) = (columns.next()?, columns.next()?, columns.next()?);
+            io.contains("->").then(|| name.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_version() {
+        assert_eq!(
+            parse_version("ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023\nbuilt with gcc"),
+            Some("6.1.1-3ubuntu5".to_string())
+        );
+        assert_eq!(parse_version("ffprobe 6.1"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn parses_codecs_below_the_rule() {
+        let output = "\
+Decoders:
+ V..... = Video
+ A..... = Audio
+ ------
+ V....D h264                 H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10
+ A....D aac                  AAC (Advanced Audio Coding)
+ S..... srt                  SubRip subtitle
+ X..... bogus                unknown kind
+";
+        let codecs = parse_codecs(output)
+            .into_iter()
+            .map(|codec| (codec.name, codec.kind, codec.description))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codecs,
+            [
+                (
+                    "h264".to_string(),
+                    CodecKind::Video,
+                    "H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10".to_string()
+                ),
+                (
+                    "aac".to_string(),
+                    CodecKind::Audio,
+                    "AAC (Advanced Audio Coding)".to_string()
+                ),
+                (
+                    "srt".to_string(),
+                    CodecKind::Subtitle,
+                    "SubRip subtitle".to_string()
+                ),
+            ]
+        );
+        assert!(parse_codecs("V..... h264 without a rule").is_empty());
+    }
+
+    #[test]
+    fn parses_filters_with_io_columns() {
+        let output = "\
+Filters:
+  T.. = Timeline support
+  | = Source or sink filter
+ ... scale             V->V       Scale the input video size and/or convert the image format.
+ T.C chromakey         V->V       Turns a certain color into transparency.
+ ... amix              N->A       Audio mixing.
+";
+        assert_eq!(parse_filters(output), ["scale", "chromakey", "amix"]);
+    }
+}
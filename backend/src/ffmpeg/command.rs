@@ -1,4 +1,4 @@
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::process::{Command, Stdio};
 
 use crate::ffmpeg::bin::ffmpeg_path;
@@ -9,7 +9,7 @@ pub(crate) fn extract_frames_rgba(
     end_frame: usize,
     dst_width: u32,
     dst_height: u32,
-    use_hwaccel: bool,
+    hwaccel: Option<&str>,
 ) -> Result<Vec<Vec<u8>>, String> {
     if end_frame < start_frame {
         return Ok(Vec::new());
@@ -32,8 +32,8 @@ pub(crate) fn extract_frames_rgba(
         .arg("-loglevel")
         .arg("error")
         .arg("-nostdin");
-    if use_hwaccel {
-        cmd.arg("-hwaccel").arg("auto");
+    if let Some(method) = hwaccel {
+        cmd.arg("-hwaccel").arg(method);
     }
     cmd.arg("-i")
         .arg(path)
@@ -89,3 +89,126 @@ pub(crate) fn extract_frames_rgba(
 
     Ok(frames)
 }
+
+/// Encode one RGBA frame into an image file; the format follows the extension of `output`.
+pub(crate) fn write_rgba_image(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    output: &str,
+) -> Result<(), String> {
+    let ffmpeg = ffmpeg_path()?;
+    let mut child = Command::new(ffmpeg)
+        .arg("-hide_banner")
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-y")
+        .arg("-f")
+        .arg("rawvideo")
+        .arg("-pix_fmt")
+        .arg("rgba")
+        .arg("-s")
+        .arg(format!("{width}x{height}"))
+        .arg("-i")
+        .arg("pipe:0")
+        .arg("-frames:v")
+        .arg("1")
+        .arg(output)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|error| format!("failed to run ffmpeg: {error}"))?;
+
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| "failed to open ffmpeg stdin".to_string())?;
+    stdin
+        .write_all(rgba)
+        .map_err(|error| format!("failed to write ffmpeg input: {error}"))?;
+    drop(stdin);
+
+    let status = child
+        .wait()
+        .map_err(|error| format!("failed to wait on ffmpeg: {error}"))?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed with status: {status}"));
+    }
+    Ok(())
+}
+
+/// Peak absolute sample value of every `samples_per_bin` sample frames of an audio stream,
+/// taken over all `channels`. Returns `bins` values.
+pub(crate) fn audio_peaks(
+    path: &str,
+    stream: &str,
+    channels: usize,
+    sample_rate: u32,
+    samples_per_bin: usize,
+    bins: usize,
+) -> Result<Vec<f32>, String> {
+    let ffmpeg = ffmpeg_path()?;
+    let mut child = Command::new(ffmpeg)
+        .arg("-hide_banner")
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-nostdin")
+        .arg("-i")
+        .arg(path)
+        .arg("-map")
+        .arg(format!("0:{stream}"))
+        .arg("-vn")
+        .arg("-ar")
+        .arg(sample_rate.to_string())
+        .arg("-f")
+        .arg("f32le")
+        .arg("-acodec")
+        .arg("pcm_f32le")
+        .arg("pipe:1")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|error| format!("failed to run ffmpeg: {error}"))?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| "failed to open ffmpeg stdout".to_string())?;
+
+    // Channels stay interleaved: downmixing would average them and lower the peaks.
+    let channels = channels.max(1);
+    let samples_per_bin = samples_per_bin.max(1);
+    let mut peaks = vec![0.0f32; bins];
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut pending = Vec::with_capacity(4);
+    let mut sample = 0usize;
+    loop {
+        let read = match stdout.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(format!("failed to read ffmpeg output: {error}")),
+        };
+        for &byte in &buffer[..read] {
+            pending.push(byte);
+            if pending.len() < 4 {
+                continue;
+            }
+            let value = f32::from_le_bytes([pending[0], pending[1], pending[2], pending[3]]);
+            pending.clear();
+            let bin = sample / channels / samples_per_bin;
+            if let Some(peak) = peaks.get_mut(bin) {
+                *peak = peak.max(value.abs());
+            }
+            sample += 1;
+        }
+    }
+
+    let status = child
+        .wait()
+        .map_err(|error| format!("failed to wait on ffmpeg: {error}"))?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed with status: {status}"));
+    }
+    Ok(peaks)
+}
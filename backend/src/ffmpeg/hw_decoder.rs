@@ -1,5 +1,6 @@
 use crate::decoder::generate_empty_frame;
 use crate::ffmpeg::command::extract_frames_rgba;
+use crate::ffmpeg::hwaccel;
 
 pub fn extract_frame_window_hw_rgba(
     path: &str,
@@ -9,24 +10,38 @@ pub fn extract_frame_window_hw_rgba(
     dst_height: u32,
 ) -> Result<Vec<(usize, Vec<u8>)>, String> {
     let end_exclusive = end_frame.saturating_add(1);
-    let frames = match extract_frames_rgba(
-        path,
-        start_frame,
-        end_exclusive,
-        dst_width,
-        dst_height,
-        true,
-    ) {
-        Ok(frames) => frames,
-        Err(hw_err) => extract_frames_rgba(
+    let software = || {
+        extract_frames_rgba(
             path,
             start_frame,
             end_exclusive,
             dst_width,
             dst_height,
-            false,
+            None,
         )
-        .map_err(|sw_err| format!("hwaccel failed: {hw_err}; software failed: {sw_err}"))?,
+    };
+    let frames = match hwaccel::method_for(path) {
+        Some(method) => match extract_frames_rgba(
+            path,
+            start_frame,
+            end_exclusive,
+            dst_width,
+            dst_height,
+            Some(&method),
+        ) {
+            Ok(frames) => frames,
+            Err(hw_err) => {
+                // A window past the end fails in software just the same; only blame the
+                // hardware when the error says so.
+                if hwaccel::is_hardware_error(&hw_err) {
+                    hwaccel::mark_failed(path);
+                }
+                software().map_err(|sw_err| {
+                    format!("hwaccel failed: {hw_err}; software failed: {sw_err}")
+                })?
+            }
+        },
+        None => software()?,
     };
 
     if frames.is_empty() {
@@ -0,0 +1,324 @@
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{LazyLock, Mutex, OnceLock},
+};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::ffmpeg::bin::ffmpeg_path;
+
+/// Which hardware decoder to use, from `FRAMESCRIPT_HWACCEL`: `auto` (the default) picks
+/// the first method that passes a test decode, `off` always decodes in software and any
+/// other value names an ffmpeg hwaccel (`vaapi`, `cuda`, ...) to use as is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "mode", content = "method", rename_all = "lowercase")]
+pub enum HwaccelPolicy {
+    Auto,
+    Off,
+    Method(String),
+}
+
+impl HwaccelPolicy {
+    fn from_env() -> Self {
+        let value = std::env::var("FRAMESCRIPT_HWACCEL").unwrap_or_default();
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "auto" => Self::Auto,
+            "off" | "none" | "0" | "false" => Self::Off,
+            method => Self::Method(method.to_string()),
+        }
+    }
+}
+
+/// Outcome of the test decode with one method.
+#[derive(Debug, Clone, Serialize)]
+pub struct MethodProbe {
+    pub method: String,
+    pub working: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HwaccelStatus {
+    pub policy: HwaccelPolicy,
+    /// The startup probe has not finished yet; streams decode in software meanwhile.
+    pub probing: bool,
+    /// Methods ffmpeg was built with, each with its test decode.
+    pub methods: Vec<MethodProbe>,
+    /// Method streams use; `None` when decoding in software.
+    pub selected: Option<String>,
+    /// Why probing failed altogether, e.g. ffmpeg is missing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Sources that failed to decode in hardware and now always decode in software.
+    #[serde(rename = "failedFiles")]
+    pub failed_files: Vec<String>,
+}
+
+struct Probe {
+    methods: Vec<MethodProbe>,
+    selected: Option<String>,
+    error: Option<String>,
+}
+
+static POLICY: LazyLock<HwaccelPolicy> = LazyLock::new(HwaccelPolicy::from_env);
+/// Set once [`probe`] has finished; streams decode in software until then.
+static PROBE: OnceLock<Probe> = OnceLock::new();
+static FAILED: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Probe the hwaccel methods. Blocks while ffmpeg runs, so the server runs it on a blocking
+/// thread at startup.
+pub fn probe() {
+    PROBE.get_or_init(run_probe);
+}
+
+/// The `-hwaccel` method to decode `path` with, or `None` to decode it in software.
+pub fn method_for(path: &str) -> Option<String> {
+    if *POLICY == HwaccelPolicy::Off || FAILED.lock().unwrap().contains(path) {
+        return None;
+    }
+    PROBE.get()?.selected.clone()
+}
+
+/// Remember that `path` failed to decode in hardware, so that later streams skip straight
+/// to software.
+pub fn mark_failed(path: &str) {
+    if FAILED.lock().unwrap().insert(path.to_string()) {
+        warn!("hardware decoding failed for {path}, using software from now on");
+    }
+}
+
+/// Words in ffmpeg errors that point at the hardware decoder rather than the source.
+const HARDWARE_ERROR_MARKERS: [&str; 12] = [
+    "hwaccel",
+    "hardware",
+    "hw_frames",
+    "hwupload",
+    "vaapi",
+    "vdpau",
+    "cuda",
+    "nvdec",
+    "qsv",
+    "videotoolbox",
+    "d3d11",
+    "dxva",
+];
+
+/// Whether an ffmpeg error message is about the hardware decoder.
+pub fn is_hardware_error(error: &str) -> bool {
+    let error = error.to_ascii_lowercase();
+    HARDWARE_ERROR_MARKERS
+        .iter()
+        .any(|marker| error.contains(marker))
+}
+
+/// Give `path` another chance in hardware, e.g. after it changed on disk.
+pub fn forget(path: &str) {
+    FAILED.lock().unwrap().remove(path);
+}
+
+pub fn status() -> HwaccelStatus {
+    let mut failed_files = FAILED.lock().unwrap().iter().cloned().collect::<Vec<_>>();
+    failed_files.sort();
+    match PROBE.get() {
+        Some(probe) => HwaccelStatus {
+            policy: POLICY.clone(),
+            probing: false,
+            methods: probe.methods.clone(),
+            selected: probe.selected.clone(),
+            error: probe.error.clone(),
+            failed_files,
+        },
+        None => HwaccelStatus {
+            policy: POLICY.clone(),
+            probing: true,
+            methods: Vec::new(),
+            selected: None,
+            error: None,
+            failed_files,
+        },
+    }
+}
+
+fn run_probe() -> Probe {
+    let policy = POLICY.clone();
+    if policy == HwaccelPolicy::Off {
+        info!("hardware decoding disabled");
+        return Probe {
+            methods: Vec::new(),
+            selected: None,
+            error: None,
+        };
+    }
+
+    let (methods, error) = match probe_methods() {
+        Ok(methods) => (methods, None),
+        Err(error) => {
+            warn!("hwaccel probe failed: {error}");
+            (Vec::new(), Some(error))
+        }
+    };
+    let selected = match &policy {
+        // An explicit method is used even when its test decode failed; the test clip may
+        // simply use a codec the hardware does not cover.
+        HwaccelPolicy::Method(method) => {
+            if !methods
+                .iter()
+                .any(|probe| probe.method == *method && probe.working)
+            {
+                warn!("FRAMESCRIPT_HWACCEL={method} did not pass the test decode");
+            }
+            Some(method.clone())
+        }
+        _ => methods
+            .iter()
+            .find(|probe| probe.working)
+            .map(|probe| probe.method.clone()),
+    };
+    match &selected {
+        Some(method) => info!("hardware decoding with {method}"),
+        None => info!("no working hwaccel, decoding in software"),
+    }
+    Probe {
+        methods,
+        selected,
+        error,
+    }
+}
+
+fn probe_methods() -> Result<Vec<MethodProbe>, String> {
+    let ffmpeg = ffmpeg_path()?;
+    let methods = list_methods(&ffmpeg)?;
+    if methods.is_empty() {
+        return Ok(Vec::new());
+    }
+    let clip = TestClip::encode(&ffmpeg)?;
+    Ok(methods
+        .into_iter()
+        .map(|method| {
+            let result = test_decode(&ffmpeg, &method, &clip.path);
+            MethodProbe {
+                working: result.is_ok(),
+                error: result.err(),
+                method,
+            }
+        })
+        .collect())
+}
+
+/// Methods listed by `ffmpeg -hwaccels`.
+pub(crate) fn list_methods(ffmpeg: &str) -> Result<Vec<String>, String> {
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-hwaccels"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| format!("failed to run ffmpeg: {error}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip_while(|line| !line.starts_with("Hardware acceleration methods"))
+        .skip(1)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// A short clip to test decode, deleted on drop.
+struct TestClip {
+    path: PathBuf,
+}
+
+impl TestClip {
+    fn encode(ffmpeg: &str) -> Result<Self, String> {
+        let path =
+            std::env::temp_dir().join(format!("framescript-hwaccel-{}.mp4", std::process::id()));
+        let clip = Self { path };
+        let mut last_error = String::new();
+        // Builds without libx264 fall back to MPEG-2, which most hardware decodes as well.
+        for codec in ["libx264", "mpeg2video"] {
+            let output = Command::new(ffmpeg)
+                .args(["-hide_banner", "-loglevel", "error", "-nostdin", "-y"])
+                .args(["-f", "lavfi", "-i", "testsrc2=size=320x240:rate=25"])
+                .args(["-frames:v", "10", "-pix_fmt", "yuv420p", "-c:v", codec])
+                .arg(&clip.path)
+                .output()
+                .map_err(|error| format!("failed to run ffmpeg: {error}"))?;
+            if output.status.success() {
+                return Ok(clip);
+            }
+            last_error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        }
+        Err(format!("failed to encode test clip: {last_error}"))
+    }
+}
+
+impl Drop for TestClip {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Decode the test clip with `method`. ffmpeg quietly falls back to software when a
+/// hwaccel fails to initialise, so anything it logs at error level counts as a failure.
+fn test_decode(ffmpeg: &str, method: &str, clip: &std::path::Path) -> Result<(), String> {
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-nostdin"])
+        .args(["-hwaccel", method, "-i"])
+        .arg(clip)
+        .args(["-f", "null", "-"])
+        .output()
+        .map_err(|error| format!("failed to run ffmpeg: {error}"))?;
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if output.status.success() && stderr.is_empty() {
+        Ok(())
+    } else if stderr.is_empty() {
+        Err(format!("ffmpeg exited with {}", output.status))
+    } else {
+        Err(stderr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_hardware_errors() {
+        assert!(is_hardware_error(
+            "[h264 @ 0x55] Failed setup for format vaapi: hwaccel initialisation returned error."
+        ));
+        assert!(is_hardware_error(
+            "Device creation failed: -542398533 (CUDA_ERROR)"
+        ));
+        assert!(is_hardware_error(
+            "Failed to transfer data to output frame: hw_frames"
+        ));
+    }
+
+    #[test]
+    fn ignores_source_errors() {
+        assert!(!is_hardware_error(
+            "[mov,mp4,m4a,3gp,3g2,mj2 @ 0x55] moov atom not found"
+        ));
+        assert!(!is_hardware_error(
+            "Invalid data found when processing input"
+        ));
+        assert!(!is_hardware_error(""));
+    }
+
+    #[test]
+    fn reports_the_policy_by_mode() {
+        let json = |policy| serde_json::to_value(policy).unwrap();
+        assert_eq!(json(HwaccelPolicy::Off), serde_json::json!({"mode": "off"}));
+        assert_eq!(
+            json(HwaccelPolicy::Method("vaapi".to_string())),
+            serde_json::json!({"mode": "method", "method": "vaapi"})
+        );
+    }
+}
@@ -0,0 +1,13 @@
+//! FrameScript's media backend: frame decoding, media probing and the HTTP/WebSocket
+//! server used by the studio and the renderer.
+
+pub mod cli;
+pub mod decoder;
+pub mod ffmpeg;
+pub mod future;
+pub mod metrics;
+pub mod server;
+pub mod util;
+
+pub use decoder::{CachedDecoder, DECODER, Decoder, DecoderKey};
+pub use server::router;
@@ -0,0 +1,304 @@
+//! Process-wide counters exposed on `GET /metrics` in the Prometheus text format.
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::decoder::{DECODER, get_cache_usage, memory::budget_status, scheduler};
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Cumulative latency histogram with fixed buckets.
+pub struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; LATENCY_BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            if seconds <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {}",
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {count}");
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let braces = |labels: &str| {
+            if labels.is_empty() {
+                String::new()
+            } else {
+                format!("{{{labels}}}")
+            }
+        };
+        let _ = writeln!(out, "{name}_sum{} {sum}", braces(labels));
+        let _ = writeln!(out, "{name}_count{} {count}", braces(labels));
+    }
+}
+
+/// How a decode stream was started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeMode {
+    Hwaccel,
+    Software,
+    /// In-process decoding through libav (software).
+    Libav,
+}
+
+impl DecodeMode {
+    const ALL: [Self; 3] = [Self::Hwaccel, Self::Software, Self::Libav];
+
+    pub fn from_hwaccel(use_hwaccel: bool) -> Self {
+        if use_hwaccel {
+            Self::Hwaccel
+        } else {
+            Self::Software
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Hwaccel => "hwaccel",
+            Self::Software => "software",
+            Self::Libav => "libav",
+        }
+    }
+}
+
+/// What a frame request was answered with when decoding did not deliver it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// The closest earlier decoded frame.
+    PreviousFrame,
+    /// A one-shot ffmpeg extraction of the frame.
+    Extract,
+    /// A solid placeholder (or transparent frame for alpha sources).
+    Placeholder,
+}
+
+impl Fallback {
+    const ALL: [Self; 3] = [Self::PreviousFrame, Self::Extract, Self::Placeholder];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::PreviousFrame => "previous_frame",
+            Self::Extract => "extract",
+            Self::Placeholder => "placeholder",
+        }
+    }
+}
+
+/// Per-mode counters, indexed by [`DecodeMode`].
+struct ModeCounter([AtomicU64; 3]);
+
+impl ModeCounter {
+    const fn new() -> Self {
+        Self([AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)])
+    }
+
+    fn inc(&self, mode: DecodeMode) {
+        self.0[mode as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self, mode: DecodeMode) -> u64 {
+        self.0[mode as usize].load(Ordering::Relaxed)
+    }
+}
+
+static FFMPEG_SPAWNS: ModeCounter = ModeCounter::new();
+static FFMPEG_RESTARTS: ModeCounter = ModeCounter::new();
+static FALLBACKS: [AtomicU64; 3] = [const { AtomicU64::new(0) }; 3];
+static SESSIONS: AtomicU64 = AtomicU64::new(0);
+/// Time from a `/ws` frame request to its packet being ready.
+pub static FRAME_REQUEST_LATENCY: Histogram = Histogram::new();
+/// Time a decoder takes to hand out a frame, split by whether it was already cached.
+pub static FRAME_CACHED_LATENCY: Histogram = Histogram::new();
+pub static FRAME_DECODED_LATENCY: Histogram = Histogram::new();
+
+/// A decode stream was started.
+pub fn record_spawn(mode: DecodeMode) {
+    FFMPEG_SPAWNS.inc(mode);
+}
+
+/// A running decode process was replaced (seek, speed change or hwaccel failure).
+pub fn record_restart(mode: DecodeMode) {
+    FFMPEG_RESTARTS.inc(mode);
+}
+
+pub fn record_fallback(fallback: Fallback) {
+    FALLBACKS[fallback as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn session_opened() {
+    SESSIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn session_closed() {
+    SESSIONS.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Append a `# HELP` / `# TYPE` header.
+pub fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Append an unlabelled gauge.
+pub fn gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    header(out, name, "gauge", help);
+    let _ = writeln!(out, "{name} {value}");
+}
+
+/// Decoder, cache and ffmpeg metrics. The server appends its own render metrics.
+pub fn render() -> String {
+    let mut out = String::new();
+    let (used, limit) = get_cache_usage();
+    gauge(
+        &mut out,
+        "framescript_cache_bytes",
+        "Bytes of decoded frames held in memory.",
+        used,
+    );
+    gauge(
+        &mut out,
+        "framescript_cache_limit_bytes",
+        "Memory budget for decoded frames, lowered under memory pressure.",
+        limit,
+    );
+    let budget = budget_status();
+    gauge(
+        &mut out,
+        "framescript_cache_max_bytes",
+        "Configured upper bound of the cache budget.",
+        budget.max_bytes,
+    );
+    if let Some(available) = budget.available_bytes {
+        gauge(
+            &mut out,
+            "framescript_memory_available_bytes",
+            "Memory available to the process (system or cgroup).",
+            available,
+        );
+    }
+    gauge(
+        &mut out,
+        "framescript_decoders",
+        "Live decoders, one per source, size and format.",
+        DECODER.len(),
+    );
+    gauge(
+        &mut out,
+        "framescript_sessions",
+        "Connected /ws clients.",
+        SESSIONS.load(Ordering::Relaxed),
+    );
+
+    let slots = scheduler::status();
+    gauge(
+        &mut out,
+        "framescript_ffmpeg_running",
+        "Decode processes holding a scheduler slot.",
+        slots.running,
+    );
+    gauge(
+        &mut out,
+        "framescript_ffmpeg_max",
+        "Cap on concurrent decode processes.",
+        slots.max_processes,
+    );
+    gauge(
+        &mut out,
+        "framescript_ffmpeg_waiting",
+        "Decodes waiting for a scheduler slot.",
+        slots.waiting.values().sum::<usize>(),
+    );
+
+    for (name, help, counter) in [
+        (
+            "framescript_ffmpeg_spawns_total",
+            "Decode streams started (ffmpeg processes or libav).",
+            &FFMPEG_SPAWNS,
+        ),
+        (
+            "framescript_ffmpeg_restarts_total",
+            "Decode processes replaced by a new one.",
+            &FFMPEG_RESTARTS,
+        ),
+    ] {
+        header(&mut out, name, "counter", help);
+        for mode in DecodeMode::ALL {
+            let _ = writeln!(
+                out,
+                "{name}{{mode=\"{}\"}} {}",
+                mode.label(),
+                counter.get(mode)
+            );
+        }
+    }
+
+    let name = "framescript_frame_fallbacks_total";
+    header(
+        &mut out,
+        name,
+        "counter",
+        "Frame requests answered without a decoded frame.",
+    );
+    for fallback in Fallback::ALL {
+        let _ = writeln!(
+            out,
+            "{name}{{kind=\"{}\"}} {}",
+            fallback.label(),
+            FALLBACKS[fallback as usize].load(Ordering::Relaxed)
+        );
+    }
+
+    let name = "framescript_frame_request_seconds";
+    header(
+        &mut out,
+        name,
+        "histogram",
+        "Latency of /ws frame requests.",
+    );
+    FRAME_REQUEST_LATENCY.write(&mut out, name, "");
+
+    let name = "framescript_decoder_frame_seconds";
+    header(
+        &mut out,
+        name,
+        "histogram",
+        "Time for a decoder to return a frame.",
+    );
+    FRAME_CACHED_LATENCY.write(&mut out, name, "cache=\"hit\"");
+    FRAME_DECODED_LATENCY.write(&mut out, name, "cache=\"miss\"");
+
+    out
+}
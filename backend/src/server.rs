@@ -0,0 +1,1453 @@
+use std::{
+    net::SocketAddr,
+    ops::Bound,
+    path::PathBuf,
+    sync::{Arc, atomic::AtomicBool},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{
+        Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    serve,
+};
+use axum_extra::{TypedHeader, headers::Range};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+use tokio::net::TcpListener;
+use tokio_util::io::ReaderStream;
+use tracing::{error, info};
+
+use crate::{
+    decoder::{
+        DECODER, DecoderKey, OutOfRangePolicy,
+        cache::{self, DecoderUsage, SessionUsage, default_session_quota, set_session_quota},
+        color::ColorOptions,
+        deinterlace::{DeinterlaceOptions, Deinterlacer, FieldRate},
+        disk_cache::{self, DiskCacheStatus},
+        filter::{VideoFilter, cropped_dimensions},
+        get_cache_usage,
+        memory::{BudgetStatus, budget_status, set_adaptive, set_min_cache_size},
+        pixel_format::{ColorMatrix, PixelFormat},
+        placeholder_frame,
+        proxy::{self, ProxyCodec},
+        render_session::{self, ClipMapping, WorkerRange},
+        resample::{FitMode, ScaleAlgorithm, resample_frame},
+        scheduler::{self, Priority},
+        set_max_cache_size,
+        source::{ImageSequence, sequence_for},
+        source_dimensions, watch,
+    },
+    ffmpeg::{
+        StreamSelector,
+        capabilities::{self, FfmpegCapabilities},
+        hwaccel::{self, HwaccelStatus},
+        probe_audio_duration_ms, probe_streams, probe_video_codec, probe_video_duration_ms,
+        probe_video_field_order, probe_video_fps, probe_video_frames, probe_video_geometry,
+    },
+    metrics::{self, Fallback},
+    util::{KeyFloat, resolve_path_to_string},
+};
+
+#[derive(Deserialize)]
+struct VideoQuery {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct VideoMetaQuery {
+    path: String,
+    /// Only used when `path` is an image sequence pattern.
+    #[serde(rename = "startNumber")]
+    start_number: Option<u32>,
+    fps: Option<f64>,
+    /// Video stream by position among video streams.
+    stream: Option<u32>,
+    /// Same as the `/ws` deinterlace options; `fps` and `frame_count` follow the rate.
+    deinterlace: Option<Deinterlacer>,
+    #[serde(rename = "deinterlaceRate")]
+    deinterlace_rate: Option<FieldRate>,
+}
+
+#[derive(Deserialize)]
+struct AudioQuery {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct AudioMetaQuery {
+    path: String,
+    /// Audio stream by position among audio streams, or by language tag.
+    stream: Option<u32>,
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FileQuery {
+    path: String,
+}
+
+#[derive(Clone)]
+struct AppState;
+
+#[derive(Deserialize, Debug)]
+struct FrameRequest {
+    video: String,
+    width: u32,
+    height: u32,
+    frame: u32,
+    #[serde(rename = "outOfRange")]
+    out_of_range: Option<OutOfRangePolicy>,
+    #[serde(rename = "playbackRate")]
+    playback_rate: Option<f64>,
+    scale: Option<ScaleAlgorithm>,
+    fit: Option<FitMode>,
+    format: Option<PixelFormat>,
+    color: Option<ColorOptions>,
+    filters: Option<Vec<VideoFilter>>,
+    /// Numbering and frame rate when `video` is a sequence pattern like `shot_%04d.png`.
+    sequence: Option<ImageSequence>,
+    /// Defaults to deinterlacing sources flagged as interlaced at frame rate.
+    deinterlace: Option<DeinterlaceOptions>,
+    /// Video stream to decode. Defaults to the first one.
+    stream: Option<StreamSelector>,
+    /// Allow decoding from a proxy (see `/proxy`) when one covers the requested size.
+    /// Renders leave this off so they always read the original.
+    #[serde(default)]
+    preview: bool,
+    /// Scheduling class when ffmpeg slots run short. Defaults to `interactive`.
+    priority: Option<Priority>,
+    /// Render worker this request comes from (see `/render_session`). Together with `clip`
+    /// it lets the decoder read the worker's whole range in one pass.
+    worker: Option<u32>,
+    clip: Option<ClipMapping>,
+}
+
+#[derive(Deserialize)]
+struct CacheSizeRequest {
+    gib: usize,
+}
+
+#[derive(Deserialize)]
+struct CacheRequest {
+    /// New memory budget. `bytes` wins over `mib`.
+    bytes: Option<usize>,
+    mib: Option<usize>,
+    /// Floor of the adaptive budget. `minBytes` wins over `minMib`.
+    #[serde(rename = "minBytes")]
+    min_bytes: Option<usize>,
+    #[serde(rename = "minMib")]
+    min_mib: Option<usize>,
+    /// Whether the budget follows available memory. On by default.
+    adaptive: Option<bool>,
+    /// Quota for `session`, or for every session when `session` is omitted. 0 removes it.
+    #[serde(rename = "sessionQuotaBytes")]
+    session_quota_bytes: Option<usize>,
+    #[serde(rename = "sessionQuotaMib")]
+    session_quota_mib: Option<usize>,
+    session: Option<u64>,
+    /// Size limit of the disk cache. 0 disables it.
+    #[serde(rename = "diskMib")]
+    disk_mib: Option<u64>,
+    #[serde(rename = "diskDir")]
+    disk_dir: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CacheResponse {
+    #[serde(rename = "usedBytes")]
+    used_bytes: usize,
+    #[serde(rename = "limitBytes")]
+    limit_bytes: usize,
+    budget: BudgetStatus,
+    #[serde(rename = "sessionQuotaBytes")]
+    session_quota_bytes: Option<usize>,
+    decoders: Vec<DecoderUsage>,
+    sessions: Vec<SessionUsage>,
+    disk: DiskCacheStatus,
+}
+
+/// Decoders to drop. Both fields narrow the purge; an empty request drops every decoder.
+#[derive(Deserialize)]
+struct CachePurgeRequest {
+    path: Option<String>,
+    session: Option<u64>,
+    /// Also empty the disk cache.
+    #[serde(default)]
+    disk: bool,
+}
+
+#[derive(Serialize)]
+struct CachePurgeResponse {
+    decoders: usize,
+    #[serde(rename = "freedBytes")]
+    freed_bytes: usize,
+    #[serde(rename = "freedDiskBytes")]
+    freed_disk_bytes: u64,
+}
+
+#[derive(Deserialize)]
+struct SchedulerRequest {
+    #[serde(rename = "maxProcesses")]
+    max_processes: usize,
+}
+
+#[derive(Deserialize)]
+struct ProxyQuery {
+    path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProxyRequest {
+    path: String,
+    /// Proxy height in pixels. Defaults to 540.
+    height: Option<u32>,
+    codec: Option<ProxyCodec>,
+}
+
+#[derive(Deserialize)]
+struct RenderSessionRequest {
+    workers: Vec<WorkerRange>,
+}
+
+#[derive(Serialize)]
+struct RenderSessionResponse {
+    workers: Vec<WorkerRange>,
+}
+
+#[derive(Deserialize)]
+struct ProgressRequest {
+    completed: Option<usize>,
+    total: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ProgressResponse {
+    completed: usize,
+    total: usize,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum AudioSourceRef {
+    Video { path: String },
+    Sound { path: String },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+enum AudioLoudnessPreset {
+    Youtube,
+}
+
+/// Remapping of a segment's channels onto the stereo mix.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum AudioChannelMap {
+    /// Left channel on both sides.
+    Left,
+    /// Right channel on both sides.
+    Right,
+    /// Average of all channels on both sides.
+    Mono,
+    /// Left and right exchanged.
+    Swap,
+}
+
+#[derive(Deserialize, Clone)]
+struct AudioSegment {
+    id: String,
+    source: AudioSourceRef,
+    #[serde(rename = "projectStartFrame")]
+    project_start_frame: i64,
+    #[serde(rename = "sourceStartFrame")]
+    source_start_frame: i64,
+    #[serde(rename = "durationFrames")]
+    duration_frames: i64,
+    #[serde(rename = "fadeInFrames")]
+    fade_in_frames: Option<i64>,
+    #[serde(rename = "fadeOutFrames")]
+    fade_out_frames: Option<i64>,
+    volume: Option<f64>,
+    /// Audio stream of the source. Defaults to the first one.
+    stream: Option<StreamSelector>,
+    channels: Option<AudioChannelMap>,
+}
+
+#[derive(Deserialize, Clone)]
+struct AudioPlanRequest {
+    fps: f64,
+    segments: Vec<AudioSegment>,
+    loudness: Option<AudioLoudnessPreset>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum AudioSourceResolved {
+    Video { path: String },
+    Sound { path: String },
+}
+
+#[derive(Serialize, Clone)]
+struct AudioSegmentResolved {
+    id: String,
+    source: AudioSourceResolved,
+    #[serde(rename = "projectStartFrame")]
+    project_start_frame: i64,
+    #[serde(rename = "sourceStartFrame")]
+    source_start_frame: i64,
+    #[serde(rename = "durationFrames")]
+    duration_frames: i64,
+    #[serde(rename = "fadeInFrames")]
+    fade_in_frames: i64,
+    #[serde(rename = "fadeOutFrames")]
+    fade_out_frames: i64,
+    volume: f64,
+    /// ffmpeg stream specifier within the source, e.g. `a:1`.
+    stream: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channels: Option<AudioChannelMap>,
+}
+
+#[derive(Serialize, Clone)]
+struct AudioPlanResolved {
+    fps: f64,
+    segments: Vec<AudioSegmentResolved>,
+    loudness: Option<AudioLoudnessPreset>,
+}
+
+#[derive(Deserialize)]
+struct RenderLogRequest {
+    message: String,
+    level: Option<String>,
+    session: Option<String>,
+    context: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Clone)]
+struct RenderLogEntry {
+    timestamp_ms: u64,
+    message: String,
+    level: String,
+    session: Option<String>,
+    context: Option<serde_json::Value>,
+}
+
+static RENDER_AUDIO_PLAN: std::sync::LazyLock<std::sync::Mutex<Option<AudioPlanResolved>>> =
+    std::sync::LazyLock::new(|| std::sync::Mutex::new(None));
+static RENDER_LOGS: std::sync::LazyLock<std::sync::Mutex<Vec<RenderLogEntry>>> =
+    std::sync::LazyLock::new(|| std::sync::Mutex::new(Vec::new()));
+
+static RENDER_COMPLETED: AtomicUsize = AtomicUsize::new(0);
+static RENDER_TOTAL: AtomicUsize = AtomicUsize::new(0);
+static RENDER_CANCEL: AtomicBool = AtomicBool::new(false);
+static NEXT_SESSION_ID: AtomicUsize = AtomicUsize::new(1);
+const MAX_RENDER_LOGS: usize = 2000;
+/// Version of the HTTP and `/ws` protocol, bumped on incompatible changes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Router with every HTTP and WebSocket endpoint of the backend.
+pub fn router() -> Router {
+    let app_state = AppState;
+    Router::new()
+        .route("/ws", get(ws_handler))
+        .route("/video", get(video_handler).options(options_handler))
+        .route(
+            "/video/meta",
+            get(video_meta_handler).options(options_handler),
+        )
+        .route("/audio", get(audio_handler).options(options_handler))
+        .route(
+            "/audio/meta",
+            get(audio_meta_handler).options(options_handler),
+        )
+        .route("/streams", get(streams_handler).options(options_handler))
+        .route("/file", get(file_handler).options(options_handler))
+        .route(
+            "/set_cache_size",
+            post(set_cache_size_handler).options(options_handler),
+        )
+        .route(
+            "/cache",
+            get(get_cache_handler)
+                .post(set_cache_handler)
+                .options(options_handler),
+        )
+        .route(
+            "/cache/purge",
+            post(purge_cache_handler).options(options_handler),
+        )
+        .route(
+            "/scheduler",
+            get(get_scheduler_handler)
+                .post(set_scheduler_handler)
+                .options(options_handler),
+        )
+        .route(
+            "/proxy",
+            get(get_proxy_handler)
+                .post(request_proxy_handler)
+                .options(options_handler),
+        )
+        .route(
+            "/render_session",
+            post(set_render_session_handler)
+                .get(get_render_session_handler)
+                .options(options_handler),
+        )
+        .route(
+            "/render_progress",
+            post(set_progress_handler)
+                .get(get_progress_handler)
+                .options(options_handler),
+        )
+        .route(
+            "/render_log",
+            post(render_log_handler)
+                .get(get_render_log_handler)
+                .options(options_handler),
+        )
+        .route(
+            "/render_cancel",
+            post(render_cancel_handler).options(options_handler),
+        )
+        .route(
+            "/render_audio_plan",
+            post(set_audio_plan_handler)
+                .get(get_audio_plan_handler)
+                .options(options_handler),
+        )
+        .route("/reset", post(reset_handler).options(options_handler))
+        .route(
+            "/is_canceled",
+            get(is_canceled_handler).options(options_handler),
+        )
+        .route(
+            "/capabilities",
+            get(capabilities_handler).options(options_handler),
+        )
+        .route("/healthz", get(healthz_handler).options(options_handler))
+        .route("/metrics", get(metrics_handler).options(options_handler))
+        .with_state(app_state)
+}
+
+/// Serve [`router`] on `addr` until the process exits.
+pub async fn run(addr: SocketAddr) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("listening on {addr}");
+    // Find a working hardware decoder before the first frame is requested.
+    tokio::task::spawn_blocking(hwaccel::probe);
+    println!("[backend ready] listening on {addr}");
+
+    serve(listener, router()).await
+}
+
+async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| handle_socket(socket, state))
+}
+
+async fn video_handler(
+    State(_state): State<AppState>,
+    Query(VideoQuery { path }): Query<VideoQuery>,
+    range: Option<TypedHeader<Range>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let resolved_path = resolve_path_to_string(&path).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut file = tokio::fs::File::open(&resolved_path)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let metadata = file
+        .metadata()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let len = metadata.len();
+
+    let (status, body, content_range, content_length) = if let Some(TypedHeader(range)) = range {
+        let mut iter = range.satisfiable_ranges(len);
+
+        if let Some((start_bound, end_bound)) = iter.next() {
+            let start = match start_bound {
+                Bound::Included(n) => n,
+                Bound::Excluded(n) => n + 1,
+                Bound::Unbounded => 0,
+            };
+
+            let end = match end_bound {
+                Bound::Included(n) => n,
+                Bound::Excluded(n) => n.saturating_sub(1),
+                Bound::Unbounded => len.saturating_sub(1),
+            };
+
+            if start >= len || end >= len || start > end {
+                return Err(StatusCode::RANGE_NOT_SATISFIABLE);
+            }
+
+            let chunk_size = end - start + 1;
+
+            file.seek(SeekFrom::Start(start))
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+            let stream = ReaderStream::with_capacity(file.take(chunk_size), 16 * 1024);
+            let range_header = format!("bytes {}-{}/{}", start, end, len);
+
+            (
+                StatusCode::PARTIAL_CONTENT,
+                stream,
+                Some(range_header),
+                chunk_size,
+            )
+        } else {
+            return Err(StatusCode::RANGE_NOT_SATISFIABLE);
+        }
+    } else {
+        // Range ヘッダなし => 全体を返す
+        let stream = ReaderStream::with_capacity(file.take(len), 16 * 1024);
+        (StatusCode::OK, stream, None, len)
+    };
+
+    let mut resp = axum::response::Response::new(axum::body::Body::from_stream(body));
+    *resp.status_mut() = status;
+
+    let headers = resp.headers_mut();
+    headers.insert(
+        header::ACCEPT_RANGES,
+        header::HeaderValue::from_static("bytes"),
+    );
+    if let Ok(v) = header::HeaderValue::from_str(&content_length.to_string()) {
+        headers.insert(header::CONTENT_LENGTH, v);
+    }
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("video/mp4"),
+    );
+    if let Some(range_str) = content_range {
+        headers.insert(
+            header::CONTENT_RANGE,
+            header::HeaderValue::from_str(&range_str)
+                .unwrap_or_else(|_| header::HeaderValue::from_static("bytes */*")),
+        );
+    }
+    apply_cors(headers);
+
+    Ok(resp)
+}
+
+async fn audio_handler(
+    State(_state): State<AppState>,
+    Query(AudioQuery { path }): Query<AudioQuery>,
+    range: Option<TypedHeader<Range>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let resolved_path = resolve_path_to_string(&path).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut file = tokio::fs::File::open(&resolved_path)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let metadata = file
+        .metadata()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let len = metadata.len();
+
+    let (status, body, content_range, content_length) = if let Some(TypedHeader(range)) = range {
+        let mut iter = range.satisfiable_ranges(len);
+
+        if let Some((start_bound, end_bound)) = iter.next() {
+            let start = match start_bound {
+                Bound::Included(n) => n,
+                Bound::Excluded(n) => n + 1,
+                Bound::Unbounded => 0,
+            };
+
+            let end = match end_bound {
+                Bound::Included(n) => n,
+                Bound::Excluded(n) => n.saturating_sub(1),
+                Bound::Unbounded => len.saturating_sub(1),
+            };
+
+            if start >= len || end >= len || start > end {
+                return Err(StatusCode::RANGE_NOT_SATISFIABLE);
+            }
+
+            let chunk_size = end - start + 1;
+
+            file.seek(SeekFrom::Start(start))
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+            let stream = ReaderStream::with_capacity(file.take(chunk_size), 16 * 1024);
+            let range_header = format!("bytes {}-{}/{}", start, end, len);
+
+            (
+                StatusCode::PARTIAL_CONTENT,
+                stream,
+                Some(range_header),
+                chunk_size,
+            )
+        } else {
+            return Err(StatusCode::RANGE_NOT_SATISFIABLE);
+        }
+    } else {
+        // Range ヘッダなし => 全体を返す
+        let stream = ReaderStream::with_capacity(file.take(len), 16 * 1024);
+        (StatusCode::OK, stream, None, len)
+    };
+
+    let mut resp = axum::response::Response::new(axum::body::Body::from_stream(body));
+    *resp.status_mut() = status;
+
+    let headers = resp.headers_mut();
+    headers.insert(
+        header::ACCEPT_RANGES,
+        header::HeaderValue::from_static("bytes"),
+    );
+    if let Ok(v) = header::HeaderValue::from_str(&content_length.to_string()) {
+        headers.insert(header::CONTENT_LENGTH, v);
+    }
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("audio/mp4"),
+    );
+    if let Some(range_str) = content_range {
+        headers.insert(
+            header::CONTENT_RANGE,
+            header::HeaderValue::from_str(&range_str)
+                .unwrap_or_else(|_| header::HeaderValue::from_static("bytes */*")),
+        );
+    }
+    apply_cors(headers);
+
+    Ok(resp)
+}
+
+fn cors_status(status: StatusCode) -> axum::response::Response {
+    let mut resp = axum::response::Response::new(axum::body::Body::empty());
+    *resp.status_mut() = status;
+    apply_cors(resp.headers_mut());
+    resp
+}
+
+async fn file_handler(
+    State(_state): State<AppState>,
+    Query(FileQuery { path }): Query<FileQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let resolved_path = match resolve_path_to_string(&path) {
+        Ok(value) => value,
+        Err(_) => return Ok(cors_status(StatusCode::BAD_REQUEST)),
+    };
+    let file = match tokio::fs::File::open(&resolved_path).await {
+        Ok(value) => value,
+        Err(_) => return Ok(cors_status(StatusCode::NOT_FOUND)),
+    };
+    let metadata = match file.metadata().await {
+        Ok(value) => value,
+        Err(_) => return Ok(cors_status(StatusCode::INTERNAL_SERVER_ERROR)),
+    };
+    let len = metadata.len();
+
+    let stream = ReaderStream::with_capacity(file.take(len), 16 * 1024);
+    let mut resp = axum::response::Response::new(axum::body::Body::from_stream(stream));
+    *resp.status_mut() = StatusCode::OK;
+
+    let headers = resp.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/octet-stream"),
+    );
+    if let Ok(v) = header::HeaderValue::from_str(&len.to_string()) {
+        headers.insert(header::CONTENT_LENGTH, v);
+    }
+    apply_cors(headers);
+
+    Ok(resp)
+}
+
+/// What the local ffmpeg can do, as reported by `GET /capabilities`.
+#[derive(Serialize)]
+struct Capabilities {
+    /// Version of this backend.
+    version: &'static str,
+    #[serde(rename = "protocolVersion")]
+    protocol_version: u32,
+    #[serde(flatten)]
+    ffmpeg: FfmpegCapabilities,
+    hwaccel: HwaccelStatus,
+}
+
+async fn capabilities_handler() -> Result<impl IntoResponse, StatusCode> {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    let (ffmpeg, hwaccel) =
+        tokio::task::spawn_blocking(|| (capabilities::capabilities(), hwaccel::status()))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((
+        headers,
+        Json(Capabilities {
+            version: env!("CARGO_PKG_VERSION"),
+            protocol_version: PROTOCOL_VERSION,
+            ffmpeg: (*ffmpeg).clone(),
+            hwaccel,
+        }),
+    ))
+}
+
+async fn healthz_handler() -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    (headers, StatusCode::OK)
+}
+
+#[derive(Serialize)]
+struct VideoMetadataResponse {
+    duration_ms: u64,
+    fps: f64,
+    frame_count: u64,
+    width: u32,
+    height: u32,
+    /// Clockwise rotation already applied to `width`/`height` and to decoded frames.
+    rotation: u32,
+    /// Whether decoded frames carry real per-pixel alpha.
+    alpha: bool,
+    /// Whether the source is flagged as interlaced.
+    interlaced: bool,
+}
+
+async fn video_meta_handler(
+    State(_state): State<AppState>,
+    Query(query): Query<VideoMetaQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let path = resolve_path_to_string(&query.path).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let sequence = sequence_for(
+        &path,
+        Some(ImageSequence {
+            start_number: query.start_number,
+            fps: query.fps.map(KeyFloat),
+        }),
+    );
+
+    let stream = StreamSelector {
+        index: query.stream,
+        language: None,
+    }
+    .specifier('v');
+
+    let (resolved_path, duration_ms, fps, frame_count) = match sequence {
+        Some(sequence) => {
+            let frame_count = sequence.frame_count(&path);
+            if frame_count == 0 {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            let duration_ms = (frame_count as f64 * 1000.0 / sequence.fps()).round() as u64;
+            (
+                sequence.first_file(&path),
+                duration_ms,
+                sequence.fps(),
+                frame_count as u64,
+            )
+        }
+        None => {
+            let duration_ms =
+                probe_video_duration_ms(&path, &stream).map_err(|_| StatusCode::BAD_REQUEST)?;
+            let fps = probe_video_fps(&path, &stream).map_err(|_| StatusCode::BAD_REQUEST)?;
+            let frame_count = probe_video_frames(&path, &stream).unwrap_or(0);
+            (path, duration_ms, fps, frame_count)
+        }
+    };
+    let geometry =
+        probe_video_geometry(&resolved_path, &stream).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let (width, height) = geometry.display_dimensions();
+    let alpha = probe_video_codec(&resolved_path, &stream).is_ok_and(|codec| codec.has_alpha);
+    let field_order = probe_video_field_order(&resolved_path, &stream).unwrap_or_default();
+    let rate_multiplier = DeinterlaceOptions {
+        method: query.deinterlace,
+        rate: query.deinterlace_rate,
+    }
+    .rate_multiplier(field_order);
+
+    let mut resp = Json(VideoMetadataResponse {
+        duration_ms,
+        fps: fps * rate_multiplier as f64,
+        frame_count: frame_count * rate_multiplier as u64,
+        width,
+        height,
+        rotation: geometry.rotation,
+        alpha,
+        interlaced: field_order.is_interlaced(),
+    })
+    .into_response();
+    apply_cors(resp.headers_mut());
+    Ok(resp)
+}
+
+#[derive(Serialize)]
+struct AudioMetadataResponse {
+    duration_ms: u64,
+}
+
+async fn audio_meta_handler(
+    State(_state): State<AppState>,
+    Query(query): Query<AudioMetaQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let resolved_path = resolve_path_to_string(&query.path).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let stream = StreamSelector {
+        index: query.stream,
+        language: query.language,
+    }
+    .specifier('a');
+    let duration_ms =
+        probe_audio_duration_ms(&resolved_path, &stream).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let mut resp = Json(AudioMetadataResponse { duration_ms }).into_response();
+    apply_cors(resp.headers_mut());
+    Ok(resp)
+}
+
+async fn streams_handler(
+    State(_state): State<AppState>,
+    Query(VideoQuery { path }): Query<VideoQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let resolved_path = resolve_path_to_string(&path).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let streams = probe_streams(&resolved_path).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let mut resp = Json(streams).into_response();
+    apply_cors(resp.headers_mut());
+    Ok(resp)
+}
+
+async fn handle_socket(mut socket: WebSocket, _state: AppState) {
+    let session_id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed) as u64;
+    metrics::session_opened();
+    info!("client connected");
+    let mut media_events = watch::subscribe();
+
+    loop {
+        let msg = tokio::select! {
+            msg = socket.next() => msg,
+            event = media_events.recv() => {
+                let Ok(event) = event else {
+                    continue;
+                };
+                let text = serde_json::to_string(&event).unwrap_or_default();
+                if let Err(e) = socket.send(Message::Text(text.into())).await {
+                    error!("failed to send event: {e}");
+                    break;
+                }
+                continue;
+            }
+        };
+        let Some(msg) = msg else {
+            break;
+        };
+        let msg = match msg {
+            Ok(m) => m,
+            Err(e) => {
+                error!("ws error: {e}");
+                break;
+            }
+        };
+
+        match msg {
+            Message::Text(text) => {
+                let req: FrameRequest = match serde_json::from_str(&text) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        error!("invalid request: {e}, text={text}");
+                        continue;
+                    }
+                };
+
+                let started = Instant::now();
+                let bytes = frame_packet(req, session_id).await;
+                metrics::FRAME_REQUEST_LATENCY.observe(started.elapsed());
+
+                if let Err(e) = socket.send(Message::Binary(bytes)).await {
+                    error!("failed to send frame: {e}");
+                    break;
+                }
+            }
+            Message::Binary(_) => {}
+            Message::Ping(p) => {
+                let _ = socket.send(Message::Pong(p)).await;
+            }
+            Message::Pong(_) => {}
+            Message::Close(_) => {
+                info!("client closed");
+                break;
+            }
+        }
+    }
+
+    DECODER.clear_session(session_id);
+    metrics::session_closed();
+    info!("client disconnected");
+}
+
+/// Decode the requested frame and serialize it for `/ws`.
+///
+/// RGBA frames are sent as `[width][height][frame_index][rgba...]`. YUV frames carry three
+/// more header fields, `[width][height][frame_index][format][matrix][full_range][planes...]`,
+/// so the client can convert them on the GPU. All header fields are little-endian `u32`.
+async fn frame_packet(req: FrameRequest, session_id: u64) -> Bytes {
+    let width = req.width;
+    let height = req.height;
+    let target_frame = req.frame;
+    let format = req.format.unwrap_or_default();
+
+    let path = resolve_path_to_string(&req.video).unwrap_or_default();
+    let sequence = sequence_for(&path, req.sequence);
+    let probe_path = match sequence {
+        Some(sequence) => sequence.first_file(&path),
+        None => path.clone(),
+    };
+    let stream = req.stream.unwrap_or_default();
+    let fit = req.fit.unwrap_or_default();
+    let filters = req
+        .filters
+        .unwrap_or_default()
+        .into_iter()
+        .map(|filter| match filter {
+            VideoFilter::Lut3d { path } => VideoFilter::Lut3d {
+                path: resolve_path_to_string(&path).unwrap_or(path),
+            },
+            filter => filter,
+        })
+        .collect::<Vec<_>>();
+    let source = source_dimensions(&probe_path, &stream.specifier('v'))
+        .await
+        .map(|display| cropped_dimensions(&filters, display));
+    let mut key = DecoderKey {
+        path: path.clone(),
+        width: 0,
+        height: 0,
+        format,
+        color: req.color.unwrap_or_default(),
+        filters,
+        sequence,
+        deinterlace: req.deinterlace.unwrap_or_default(),
+        stream,
+        preview: req.preview,
+        session_id,
+    };
+    (key.width, key.height) = DECODER.decode_size(&key, source, width, height, fit);
+    let (decode_width, decode_height) = (key.width, key.height);
+
+    let decoder = DECODER.cached_decoder(key).await;
+    if let Some(rate) = req.playback_rate {
+        decoder.set_playback_rate(rate);
+    }
+    decoder.set_priority(req.priority.unwrap_or_default());
+    if let (Some(worker), Some(clip)) = (req.worker, req.clip)
+        && !decoder.has_render_plan()
+        && let Some(range) = render_session::worker_range(worker)
+    {
+        decoder.set_render_plan(render_session::plan(range, &clip));
+    }
+    let frame = decoder
+        .get_frame_with_policy(target_frame, req.out_of_range)
+        .await;
+    decoder.advance_render_plan(target_frame);
+
+    // The decoder converts every source to BT.709, limited range for YUV output.
+    let full_range = false;
+
+    let frame = if (decode_width, decode_height) == (width, height) && fit == FitMode::Stretch {
+        frame
+    } else {
+        let algorithm = req.scale.unwrap_or_default();
+        let source_scale = source
+            .map(|(source_width, _)| source_width as f64 / decode_width as f64)
+            .unwrap_or(1.0);
+        tokio::task::spawn_blocking(move || {
+            resample_frame(
+                format,
+                &frame,
+                decode_width,
+                decode_height,
+                width,
+                height,
+                algorithm,
+                fit,
+                source_scale,
+                full_range,
+            )
+        })
+        .await
+        .map(Arc::new)
+        .unwrap_or_else(|_| {
+            metrics::record_fallback(Fallback::Placeholder);
+            Arc::new(placeholder_frame(format, width, height))
+        })
+    };
+
+    let mut packet = Vec::with_capacity(24 + frame.len());
+    packet.extend_from_slice(&width.to_le_bytes());
+    packet.extend_from_slice(&height.to_le_bytes());
+    packet.extend_from_slice(&target_frame.to_le_bytes());
+    if format.is_yuv() {
+        packet.extend_from_slice(&format.code().to_le_bytes());
+        packet.extend_from_slice(&ColorMatrix::Bt709.code().to_le_bytes());
+        packet.extend_from_slice(&(full_range as u32).to_le_bytes());
+    }
+    packet.extend_from_slice(&frame);
+
+    Bytes::from(packet)
+}
+
+async fn options_handler() -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    (headers, StatusCode::NO_CONTENT)
+}
+
+async fn set_cache_size_handler(
+    State(_state): State<AppState>,
+    Json(payload): Json<CacheSizeRequest>,
+) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    let gib = payload.gib.max(1).min(128); // clamp to a sane range
+    let bytes = gib as usize * 1024 * 1024 * 1024;
+    set_max_cache_size(bytes);
+
+    (headers, StatusCode::OK)
+}
+
+fn cache_response() -> CacheResponse {
+    let (used_bytes, limit_bytes) = get_cache_usage();
+    let decoders = DECODER.usage();
+    CacheResponse {
+        used_bytes,
+        limit_bytes,
+        budget: budget_status(),
+        session_quota_bytes: default_session_quota(),
+        sessions: cache::sessions(&decoders),
+        decoders,
+        disk: disk_cache::status(),
+    }
+}
+
+async fn get_cache_handler(State(_state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    (headers, Json(cache_response()))
+}
+
+async fn set_cache_handler(
+    State(_state): State<AppState>,
+    Json(payload): Json<CacheRequest>,
+) -> Result<impl IntoResponse, (HeaderMap, StatusCode)> {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    const MIB: usize = 1024 * 1024;
+    let budget = payload
+        .bytes
+        .or_else(|| payload.mib.map(|mib| mib.saturating_mul(MIB)));
+    if let Some(bytes) = budget {
+        set_max_cache_size(bytes);
+    }
+    let min = payload
+        .min_bytes
+        .or_else(|| payload.min_mib.map(|mib| mib.saturating_mul(MIB)));
+    if let Some(bytes) = min {
+        set_min_cache_size(bytes);
+    }
+    if let Some(adaptive) = payload.adaptive {
+        set_adaptive(adaptive);
+    }
+
+    let quota = payload
+        .session_quota_bytes
+        .or_else(|| payload.session_quota_mib.map(|mib| mib.saturating_mul(MIB)));
+    if let Some(quota) = quota {
+        set_session_quota(payload.session, (quota > 0).then_some(quota));
+    }
+
+    if payload.disk_mib.is_some() || payload.disk_dir.is_some() {
+        let dir = match payload.disk_dir {
+            Some(dir) => Some(
+                resolve_path_to_string(&dir)
+                    .map(PathBuf::from)
+                    .map_err(|_| (headers.clone(), StatusCode::BAD_REQUEST))?,
+            ),
+            None => None,
+        };
+        let max_bytes = payload
+            .disk_mib
+            .map(|mib| mib.saturating_mul(MIB as u64))
+            .unwrap_or_else(|| disk_cache::status().max_bytes);
+        let configured = tokio::task::spawn_blocking(move || disk_cache::configure(dir, max_bytes))
+            .await
+            .map_err(|error| error.to_string())
+            .and_then(|result| result);
+        if let Err(error) = configured {
+            error!("disk cache setup failed: {error}");
+            return Err((headers, StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    }
+
+    Ok((headers, Json(cache_response())))
+}
+
+async fn purge_cache_handler(
+    State(_state): State<AppState>,
+    Json(payload): Json<CachePurgeRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    let path = match payload.path {
+        Some(path) => Some(resolve_path_to_string(&path).map_err(|_| StatusCode::BAD_REQUEST)?),
+        None => None,
+    };
+    let (decoders, freed_bytes) = DECODER.purge(path.as_deref(), payload.session);
+    let freed_disk_bytes = if payload.disk {
+        tokio::task::spawn_blocking(disk_cache::purge)
+            .await
+            .unwrap_or(0)
+    } else {
+        0
+    };
+    info!("cache purge: {decoders} decoders, {freed_bytes} bytes, {freed_disk_bytes} disk bytes");
+
+    Ok((
+        headers,
+        Json(CachePurgeResponse {
+            decoders,
+            freed_bytes,
+            freed_disk_bytes,
+        }),
+    ))
+}
+
+async fn get_scheduler_handler(State(_state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    (headers, Json(scheduler::status()))
+}
+
+async fn set_scheduler_handler(
+    State(_state): State<AppState>,
+    Json(payload): Json<SchedulerRequest>,
+) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    scheduler::set_max_processes(payload.max_processes);
+    (headers, Json(scheduler::status()))
+}
+
+async fn get_proxy_handler(
+    State(_state): State<AppState>,
+    Query(ProxyQuery { path }): Query<ProxyQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    let Some(path) = path else {
+        return Ok((headers, Json(proxy::list())).into_response());
+    };
+    let resolved_path = resolve_path_to_string(&path).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let status = proxy::status(&resolved_path).ok_or(StatusCode::NOT_FOUND)?;
+    Ok((headers, Json(status)).into_response())
+}
+
+async fn request_proxy_handler(
+    State(_state): State<AppState>,
+    Json(payload): Json<ProxyRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    let resolved_path =
+        resolve_path_to_string(&payload.path).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let status = proxy::request(&resolved_path, payload.height, payload.codec).await;
+    Ok((headers, Json(status)))
+}
+
+async fn set_render_session_handler(
+    State(_state): State<AppState>,
+    Json(payload): Json<RenderSessionRequest>,
+) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    render_session::register(payload.workers);
+    (headers, StatusCode::OK)
+}
+
+async fn get_render_session_handler(State(_state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    let workers = render_session::workers();
+    (headers, Json(RenderSessionResponse { workers }))
+}
+
+async fn set_progress_handler(
+    State(_state): State<AppState>,
+    Json(payload): Json<ProgressRequest>,
+) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    if let Some(total) = payload.total {
+        RENDER_TOTAL.store(total, Ordering::Relaxed);
+    }
+    if let Some(completed) = payload.completed {
+        RENDER_COMPLETED.store(
+            completed.min(RENDER_TOTAL.load(Ordering::Relaxed)),
+            Ordering::Relaxed,
+        );
+    }
+
+    (headers, StatusCode::OK)
+}
+
+async fn get_progress_handler(State(_state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    let response = ProgressResponse {
+        completed: RENDER_COMPLETED.load(Ordering::Relaxed),
+        total: RENDER_TOTAL.load(Ordering::Relaxed),
+    };
+
+    (headers, Json(response))
+}
+
+async fn metrics_handler(State(_state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"),
+    );
+
+    let mut body = metrics::render();
+    metrics::gauge(
+        &mut body,
+        "framescript_render_completed_frames",
+        "Frames finished by the current render.",
+        RENDER_COMPLETED.load(Ordering::Relaxed),
+    );
+    metrics::gauge(
+        &mut body,
+        "framescript_render_total_frames",
+        "Frames in the current render.",
+        RENDER_TOTAL.load(Ordering::Relaxed),
+    );
+    metrics::gauge(
+        &mut body,
+        "framescript_render_canceled",
+        "1 while a render cancellation is pending.",
+        RENDER_CANCEL.load(Ordering::Relaxed) as u8,
+    );
+
+    (headers, body)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+async fn render_log_handler(
+    State(_state): State<AppState>,
+    Json(payload): Json<RenderLogRequest>,
+) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    let entry = RenderLogEntry {
+        timestamp_ms: now_ms(),
+        message: payload.message,
+        level: payload.level.unwrap_or_else(|| "info".to_string()),
+        session: payload.session,
+        context: payload.context,
+    };
+
+    {
+        let mut logs = RENDER_LOGS.lock().unwrap();
+        logs.push(entry.clone());
+        if logs.len() > MAX_RENDER_LOGS {
+            let trim = logs.len() - MAX_RENDER_LOGS;
+            logs.drain(0..trim);
+        }
+    }
+
+    let session = entry.session.as_deref().unwrap_or("-");
+    let context = entry
+        .context
+        .as_ref()
+        .map(|value| value.to_string())
+        .unwrap_or_default();
+    info!(
+        "[render_log:{}] {} session={} context={}",
+        entry.level, entry.message, session, context
+    );
+
+    (headers, StatusCode::OK)
+}
+
+async fn get_render_log_handler(State(_state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    let logs = RENDER_LOGS.lock().unwrap().clone();
+    (headers, Json(logs))
+}
+
+async fn render_cancel_handler(State(_state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    RENDER_CANCEL.store(true, Ordering::Relaxed);
+    (headers, StatusCode::OK)
+}
+
+async fn is_canceled_handler(State(_state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    let canceled = RENDER_CANCEL.load(Ordering::Relaxed);
+    (headers, Json(serde_json::json!({ "canceled": canceled })))
+}
+
+async fn reset_handler(State(_state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+    DECODER.clear().await;
+    render_session::clear();
+    RENDER_CANCEL.store(false, Ordering::Relaxed);
+    *RENDER_AUDIO_PLAN.lock().unwrap() = None;
+    RENDER_LOGS.lock().unwrap().clear();
+    (headers, StatusCode::OK)
+}
+
+async fn set_audio_plan_handler(
+    State(_state): State<AppState>,
+    Json(payload): Json<AudioPlanRequest>,
+) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    let fps = if payload.fps.is_finite() && payload.fps > 0.0 {
+        payload.fps
+    } else {
+        60.0
+    };
+
+    let mut segments = Vec::new();
+    for seg in payload.segments.into_iter() {
+        let duration_frames = seg.duration_frames.max(0);
+        if duration_frames == 0 {
+            continue;
+        }
+
+        let project_start_frame = seg.project_start_frame.max(0);
+        let source_start_frame = seg.source_start_frame.max(0);
+
+        let resolved_source = match seg.source {
+            AudioSourceRef::Video { path } => resolve_path_to_string(&path)
+                .ok()
+                .map(|p| AudioSourceResolved::Video { path: p }),
+            AudioSourceRef::Sound { path } => resolve_path_to_string(&path)
+                .ok()
+                .map(|p| AudioSourceResolved::Sound { path: p }),
+        };
+
+        let Some(source) = resolved_source else {
+            continue;
+        };
+
+        // Validate that the source actually has an audio stream, and clamp the segment to its duration.
+        let source_path = match &source {
+            AudioSourceResolved::Video { path } => path.as_str(),
+            AudioSourceResolved::Sound { path } => path.as_str(),
+        };
+        let stream = seg.stream.unwrap_or_default().specifier('a');
+        let source_duration_ms = match probe_audio_duration_ms(source_path, &stream) {
+            Ok(ms) if ms > 0 => ms,
+            _ => continue,
+        };
+        let source_total_frames = ((source_duration_ms as f64 / 1000.0) * fps)
+            .round()
+            .max(0.0) as i64;
+        let available = (source_total_frames - source_start_frame).max(0);
+        let duration_frames = duration_frames.min(available);
+        if duration_frames == 0 {
+            continue;
+        }
+
+        let fade_in_frames = seg.fade_in_frames.unwrap_or(0).max(0).min(duration_frames);
+        let fade_out_frames = seg
+            .fade_out_frames
+            .unwrap_or(0)
+            .max(0)
+            .min(duration_frames);
+        let volume = match seg.volume {
+            Some(value) if value.is_finite() => value.max(0.0),
+            _ => 1.0,
+        };
+
+        segments.push(AudioSegmentResolved {
+            id: seg.id,
+            source,
+            project_start_frame,
+            source_start_frame,
+            duration_frames,
+            fade_in_frames,
+            fade_out_frames,
+            volume,
+            stream,
+            channels: seg.channels,
+        });
+    }
+
+    *RENDER_AUDIO_PLAN.lock().unwrap() = Some(AudioPlanResolved {
+        fps,
+        segments,
+        loudness: payload.loudness,
+    });
+
+    (headers, StatusCode::OK)
+}
+
+async fn get_audio_plan_handler(State(_state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    apply_cors(&mut headers);
+
+    let plan = RENDER_AUDIO_PLAN
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or(AudioPlanResolved {
+            fps: 60.0,
+            segments: Vec::new(),
+            loudness: None,
+        });
+
+    (headers, Json(plan))
+}
+
+fn apply_cors(headers: &mut HeaderMap) {
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        HeaderValue::from_static("*"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, OPTIONS, POST"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("*"),
+    );
+}
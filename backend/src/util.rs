@@ -1,4 +1,28 @@
-use std::{env, error::Error, path::PathBuf};
+use std::{
+    env,
+    error::Error,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+
+/// A float inside a cache key such as `DecoderKey`, so that key types can derive `Eq` and
+/// `Hash`.
+///
+/// Keys are built from JSON, which cannot carry NaN, so equality is reflexive. Hashing
+/// goes by the bits, with `-0.0` folded into `0.0` since the two compare equal.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default, Deserialize)]
+#[serde(transparent)]
+pub struct KeyFloat(pub f64);
+
+impl Eq for KeyFloat {}
+
+impl Hash for KeyFloat {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0 + 0.0).to_bits().hash(state);
+    }
+}
 
 pub fn resolve_path_to_string(input: &str) -> Result<String, Box<dyn Error>> {
     let env_expanded = shellexpand::env(input)?; // -> Cow<str>
@@ -18,3 +42,35 @@ pub fn resolve_path_to_string(input: &str) -> Result<String, Box<dyn Error>> {
 
     Ok(path.to_string_lossy().into_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::hash::DefaultHasher;
+
+    use super::*;
+
+    fn hash(value: KeyFloat) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn key_float_folds_negative_zero() {
+        assert_eq!(KeyFloat(0.0), KeyFloat(-0.0));
+        assert_eq!(hash(KeyFloat(0.0)), hash(KeyFloat(-0.0)));
+    }
+
+    #[test]
+    fn key_float_distinguishes_values() {
+        assert_eq!(hash(KeyFloat(0.25)), hash(KeyFloat(0.25)));
+        assert_ne!(KeyFloat(0.25), KeyFloat(0.5));
+        assert_ne!(hash(KeyFloat(0.25)), hash(KeyFloat(0.5)));
+    }
+
+    #[test]
+    fn key_float_parses_as_a_plain_number() {
+        let value: Option<KeyFloat> = serde_json::from_str("1.5").unwrap();
+        assert_eq!(value, Some(KeyFloat(1.5)));
+    }
+}
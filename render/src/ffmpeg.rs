@@ -267,6 +267,27 @@ pub enum AudioLoudnessPreset {
     Youtube,
 }
 
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioChannelMap {
+    Left,
+    Right,
+    Mono,
+    Swap,
+}
+
+impl AudioChannelMap {
+    fn pan_filter(self) -> &'static str {
+        match self {
+            AudioChannelMap::Left => "pan=stereo|c0=c0|c1=c0",
+            AudioChannelMap::Right => "pan=stereo|c0=c1|c1=c1",
+            // Downmix first so that mono and multichannel sources both work.
+            AudioChannelMap::Mono => "aformat=channel_layouts=mono,pan=stereo|c0=c0|c1=c0",
+            AudioChannelMap::Swap => "pan=stereo|c0=c1|c1=c0",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AudioSegmentResolved {
     pub id: String,
@@ -282,6 +303,9 @@ pub struct AudioSegmentResolved {
     #[serde(rename = "fadeOutFrames")]
     pub fade_out_frames: Option<i64>,
     pub volume: Option<f64>,
+    /// Stream specifier within the source, e.g. `a:1` or `a:m:language:eng`.
+    pub stream: Option<String>,
+    pub channels: Option<AudioChannelMap>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -389,11 +413,25 @@ pub async fn mux_audio_plan_into_mp4(
         let fade_in_sec = (fade_in_frames / fps).max(0.0).min(dur_sec);
         let fade_out_sec = (fade_out_frames / fps).max(0.0).min(dur_sec);
 
+        let stream = seg
+            .stream
+            .as_deref()
+            .filter(|stream| {
+                stream.starts_with('a')
+                    && stream
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == ':' || c == '-')
+            })
+            .unwrap_or("a");
         let mut chain = format!(
-            "[{input_idx}:a]atrim=start={}:duration={},asetpts=PTS-STARTPTS,aresample=48000",
+            "[{input_idx}:{stream}]atrim=start={}:duration={},asetpts=PTS-STARTPTS,aresample=48000",
             fmt_f(start_sec),
             fmt_f(dur_sec),
         );
+        if let Some(channels) = seg.channels {
+            chain.push(',');
+            chain.push_str(channels.pan_filter());
+        }
 
         if (volume - 1.0).abs() > f64::EPSILON {
             chain.push_str(&format!(",volume={}", fmt_f(volume)));
@@ -23,6 +23,19 @@ struct ProgressPayload {
     total: usize,
 }
 
+/// Frame range of one worker, registered with the backend so it can decode ahead.
+#[derive(Serialize)]
+struct WorkerRange {
+    worker: usize,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Serialize)]
+struct RenderSessionPayload {
+    workers: Vec<WorkerRange>,
+}
+
 #[derive(Deserialize)]
 struct CancelResponse {
     canceled: bool,
@@ -369,6 +382,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    let render_session_url = std::env::var("RENDER_SESSION_URL")
+        .unwrap_or_else(|_| "http://127.0.0.1:3000/render_session".to_string());
+    let _ = progress_client
+        .post(&render_session_url)
+        .json(&RenderSessionPayload {
+            workers: ranges
+                .iter()
+                .enumerate()
+                .map(|(worker, &(start, end))| WorkerRange { worker, start, end })
+                .collect(),
+        })
+        .send()
+        .await;
+
     for (worker_id, (start, end)) in ranges.into_iter().enumerate() {
         launched_worker_ids.push(worker_id);
         let encode_clone = encode.clone();
@@ -418,6 +445,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .await
                 .map_err(|error| format!("worker {worker_id}: navigation failed: {error}"))?;
             wait_for_frame_api(&page).await;
+            // Video clips tag their frame requests with this, see `/render_session`.
+            page.evaluate(format!(
+                "(() => {{ window.__frameScript.renderWorker = {worker_id}; }})()"
+            ))
+            .await
+            .map_err(|error| format!("worker {worker_id}: renderWorker eval failed: {error}"))?;
             wait_for_animation_ready(&page).await;
             wait_for_draw_text_ready(&page).await;
             wait_for_audio_waveforms_ready(&page).await;
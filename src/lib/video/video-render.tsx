@@ -1,10 +1,11 @@
 import type { CSSProperties } from "react"
-import { useCallback, useEffect, useMemo, useRef } from "react"
+import { useCallback, useEffect, useMemo, useRef, useState } from "react"
 import { PROJECT_SETTINGS } from "../../../project/project"
 import { useCurrentFrame } from "../frame"
 import { useClipActive, useClipStart, useProvideClipDuration } from "../clip"
 import { createManualPromise, type ManualPromise } from "../../util/promise"
 import {
+  invalidate_video_meta,
   normalizeVideo,
   video_fps,
   video_frame_count,
@@ -79,12 +80,17 @@ export const VideoCanvasRender = ({
   const requestedFrameRef = useRef<number | null>(null)
   const reconnectTimerRef = useRef<number | null>(null)
   const resolved = useMemo(() => normalizeVideo(video), [video])
-  const fps = useMemo(() => video_fps(resolved), [resolved])
+  // Bumped when the source changed on disk and its metadata differs.
+  const [metaVersion, setMetaVersion] = useState(0)
+  const fps = useMemo(() => video_fps(resolved), [resolved, metaVersion])
   const sourceFrameCount = useMemo(
     () => video_frame_count(resolved),
-    [resolved],
+    [resolved, metaVersion],
+  )
+  const rawDurationFrames = useMemo(
+    () => video_length(resolved),
+    [resolved, metaVersion],
   )
-  const rawDurationFrames = useMemo(() => video_length(resolved), [resolved])
   const durationFrames = Math.max(
     0,
     rawDurationFrames - trimStartFrames - trimEndFrames,
@@ -162,6 +168,32 @@ export const VideoCanvasRender = ({
     createOrGetFramePromise(projectFrame).resolve()
   }, [])
 
+  // Source frames the clip may show, after trimming.
+  const sourceRange = useMemo(() => {
+    const sourceStart =
+      fps > 0
+        ? Math.floor((trimStartFrames * fps) / PROJECT_SETTINGS.fps)
+        : trimStartFrames
+    const sourceTrimEnd =
+      fps > 0
+        ? Math.floor((trimEndFrames * fps) / PROJECT_SETTINGS.fps)
+        : trimEndFrames
+    const estimatedSourceFrames =
+      fps > 0
+        ? Math.max(
+            0,
+            Math.round((rawDurationFrames * fps) / PROJECT_SETTINGS.fps),
+          )
+        : rawDurationFrames
+    const sourceTotalFrames =
+      sourceFrameCount > 0 ? sourceFrameCount : estimatedSourceFrames
+    const sourceEnd = Math.max(
+      sourceStart,
+      sourceTotalFrames - sourceTrimEnd - 1,
+    )
+    return { sourceStart, sourceEnd }
+  }, [fps, rawDurationFrames, sourceFrameCount, trimEndFrames, trimStartFrames])
+
   const sendPlaybackFrameRequest = useCallback(
     (playbackFrame: number) => {
       const ws = wsRef.current
@@ -180,11 +212,37 @@ export const VideoCanvasRender = ({
             ? canvasSizeRef.current.height
             : PROJECT_SETTINGS.height,
         frame: playbackFrame,
+        priority: "render",
       }
 
-      ws.send(JSON.stringify(req))
+      // Set by the render job; lets the backend decode this worker's frames ahead.
+      const renderWorker = (window as any).__frameScript?.renderWorker
+      const renderPlan =
+        typeof renderWorker === "number"
+          ? {
+              worker: renderWorker,
+              clip: {
+                start: clipStart ?? 0,
+                frames: durationFrames,
+                trimStart: trimStartFrames,
+                projectFps: PROJECT_SETTINGS.fps,
+                sourceFps: fps,
+                sourceStart: sourceRange.sourceStart,
+                sourceEnd: sourceRange.sourceEnd,
+              },
+            }
+          : {}
+
+      ws.send(JSON.stringify({ ...req, ...renderPlan }))
     },
-    [resolved.path],
+    [
+      clipStart,
+      durationFrames,
+      fps,
+      resolved.path,
+      sourceRange,
+      trimStartFrames,
+    ],
   )
 
   const sendFrameRequest = useCallback(
@@ -196,27 +254,7 @@ export const VideoCanvasRender = ({
           ? Math.min(Math.max(frame, 0), maxFrame)
           : Math.max(frame, 0)
 
-      const sourceStart =
-        fps > 0
-          ? Math.floor((trimStartFrames * fps) / PROJECT_SETTINGS.fps)
-          : trimStartFrames
-      const sourceTrimEnd =
-        fps > 0
-          ? Math.floor((trimEndFrames * fps) / PROJECT_SETTINGS.fps)
-          : trimEndFrames
-      const estimatedSourceFrames =
-        fps > 0
-          ? Math.max(
-              0,
-              Math.round((rawDurationFrames * fps) / PROJECT_SETTINGS.fps),
-            )
-          : rawDurationFrames
-      const sourceTotalFrames =
-        sourceFrameCount > 0 ? sourceFrameCount : estimatedSourceFrames
-      const sourceEnd = Math.max(
-        sourceStart,
-        sourceTotalFrames - sourceTrimEnd - 1,
-      )
+      const { sourceStart, sourceEnd } = sourceRange
 
       requestedFrameRef.current = clampedFrame
 
@@ -255,10 +293,8 @@ export const VideoCanvasRender = ({
     [
       durationFrames,
       fps,
-      rawDurationFrames,
       sendPlaybackFrameRequest,
-      sourceFrameCount,
-      trimEndFrames,
+      sourceRange,
       trimStartFrames,
     ],
   )
@@ -303,6 +339,38 @@ export const VideoCanvasRender = ({
       }
     }
 
+    const handleTextMessage = (text: string) => {
+      let message: { type?: unknown; path?: unknown }
+      try {
+        message = JSON.parse(text)
+      } catch {
+        return
+      }
+      if (message.type !== "media-changed" || message.path !== resolved.path) {
+        return
+      }
+
+      // The backend dropped its decoders for this file; drop what we cached too.
+      const metaKey = () =>
+        [
+          video_fps(resolved),
+          video_frame_count(resolved),
+          video_length(resolved),
+        ].join()
+      const previousMeta = metaKey()
+      invalidate_video_meta(resolved.path)
+      if (metaKey() !== previousMeta) {
+        setMetaVersion((version) => version + 1)
+      }
+
+      // Frames drawn so far show the old file.
+      lastDrawnFrameRef.current = null
+      for (const frameIndex of pendingMapRef.current.keys()) {
+        sendPlaybackFrameRequest(frameIndex)
+      }
+      sendFrameRequest(requestedFrameRef.current ?? currentFrameRef.current)
+    }
+
     const connect = () => {
       if (wsRef.current) return
       const socket = new WebSocket("ws://localhost:3000/ws")
@@ -323,6 +391,10 @@ export const VideoCanvasRender = ({
       }
 
       socket.onmessage = (event) => {
+        if (typeof event.data === "string") {
+          handleTextMessage(event.data)
+          return
+        }
         if (!(event.data instanceof ArrayBuffer)) return
         const buffer = event.data as ArrayBuffer
         const view = new DataView(buffer)
@@ -388,6 +460,7 @@ export const VideoCanvasRender = ({
     }
   }, [
     rejectPendingRequests,
+    resolved,
     resolveWaiters,
     sendFrameRequest,
     sendPlaybackFrameRequest,
@@ -135,6 +135,15 @@ const fetchVideoMetaSync = (video: Video): VideoMeta => {
   return fallback
 }
 
+/**
+ * Drops the cached metadata of `path`, e.g. after the file changed on disk.
+ *
+ * ディスク上で変更されたファイルなどのキャッシュ済みメタデータを破棄します。
+ */
+export const invalidate_video_meta = (path: string) => {
+  videoMetaCache.delete(path)
+}
+
 /**
  * Returns video length in frames (project FPS).
  *
@@ -16,14 +16,25 @@ import { useAudioSegments } from "../lib/audio-plan"
 
 const presets = ["medium", "slow", "fast"]
 const encodeOptions = [
-  { value: "H264", label: "H264 (software)" },
-  { value: "H265", label: "H265 (software)" },
+  { value: "H264", label: "H264 (software)", encoder: "libx264" },
+  { value: "H265", label: "H265 (software)", encoder: "libx265" },
 ]
 const loudnessOptions = [
   { value: "off", label: "Default", help: "Keep the mix as-is." },
-  { value: "youtube", label: "YouTube", help: "Target -14 LUFS, -1 dBTP." },
+  {
+    value: "youtube",
+    label: "YouTube",
+    help: "Target -14 LUFS, -1 dBTP.",
+    filter: "loudnorm",
+  },
 ]
 
+// What the local ffmpeg supports, from the backend's `/capabilities`.
+type FfmpegCapabilities = {
+  encoders: { name: string }[]
+  filters: string[]
+}
+
 const containerStyle: CSSProperties = {
   padding: 20,
   background: "#0b1221",
@@ -87,8 +98,34 @@ export const RenderSettingsPage = () => {
   const [platformLabel, setPlatformLabel] = useState("(detecting)")
   const [platformBinPath, setPlatformBinPath] = useState<string | null>(null)
   const [isDevMode, setIsDevMode] = useState(false)
+  const [capabilities, setCapabilities] = useState<FfmpegCapabilities | null>(
+    null,
+  )
   const audioSegments = useAudioSegments()
 
+  // Until the capabilities are known, every option is offered.
+  const availableEncodeOptions = useMemo(
+    () =>
+      encodeOptions.filter(
+        (option) =>
+          !capabilities ||
+          capabilities.encoders.some(
+            (encoder) => encoder.name === option.encoder,
+          ),
+      ),
+    [capabilities],
+  )
+  const availableLoudnessOptions = useMemo(
+    () =>
+      loudnessOptions.filter(
+        (option) =>
+          !capabilities ||
+          !option.filter ||
+          capabilities.filters.includes(option.filter),
+      ),
+    [capabilities],
+  )
+
   const commandPreview = useMemo(() => {
     return `${width}:${height}:${fps}:${frames}:${workers}:${encode}:${preset}:${ffmpegThreads}:${ffmpegLowMemory ? 1 : 0}`
   }, [
@@ -138,6 +175,33 @@ export const RenderSettingsPage = () => {
     void loadPlatform()
   }, [])
 
+  useEffect(() => {
+    const loadCapabilities = async () => {
+      try {
+        const response = await fetch("http://127.0.0.1:3000/capabilities")
+        if (response.ok) {
+          setCapabilities((await response.json()) as FfmpegCapabilities)
+        }
+      } catch (_error) {
+        // Backend not up yet; keep offering everything.
+      }
+    }
+    void loadCapabilities()
+  }, [])
+
+  useEffect(() => {
+    const available = availableEncodeOptions.map((option) => option.value)
+    if (available.length > 0 && !available.includes(encode)) {
+      setEncode(available[0] as "H264" | "H265")
+    }
+  }, [availableEncodeOptions, encode])
+
+  useEffect(() => {
+    if (!availableLoudnessOptions.some((option) => option.value === loudness)) {
+      setLoudness("off")
+    }
+  }, [availableLoudnessOptions, loudness])
+
   const handleDurationUpdate = useCallback(
     (value: number) => {
       if (value > 0) {
@@ -346,7 +410,7 @@ export const RenderSettingsPage = () => {
         <div style={sectionStyle}>
           <div style={sectionTitleStyle}>Encoding</div>
           <div style={{ display: "flex", gap: 12, flexWrap: "wrap" }}>
-            {encodeOptions.map((option) => (
+            {availableEncodeOptions.map((option) => (
               <label
                 key={option.value}
                 style={{
@@ -402,7 +466,7 @@ export const RenderSettingsPage = () => {
           <div style={sectionTitleStyle}>Audio</div>
           <div style={{ fontSize: 12, color: "#cbd5e1" }}>Loudness</div>
           <div style={{ display: "flex", gap: 12, flexWrap: "wrap" }}>
-            {loudnessOptions.map((option) => (
+            {availableLoudnessOptions.map((option) => (
               <label
                 key={option.value}
                 style={{